@@ -0,0 +1,33 @@
+// This example lists the hubs around as they advertise, and connects to the first one whose button is pressed
+//
+//
+//
+
+use anyhow::Result;
+use tokio_stream::StreamExt;
+
+use rust_powered_lego::{
+    connection_manager::ConnectionManager,
+    HubType,
+};
+
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cm = ConnectionManager::new();
+    let mut hubs = cm.discover().await?;
+
+    println!("Press the button of the hub to connect to...");
+    while let Some(discovered) = hubs.next().await {
+        println!("{} {:?} ({:?}, rssi {:?})", discovered.address, discovered.name, discovered.hub_type, discovered.rssi);
+        if discovered.button_pressed {
+            // Scanning stops with the stream
+            drop(hubs);
+            let hub = discovered.connect().await?;
+            println!("Connected, attached devices: {:?}", hub.get_attached_devices().await?.keys());
+            break;
+        }
+    }
+
+    Ok(())
+}
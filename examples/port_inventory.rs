@@ -0,0 +1,34 @@
+// This example prints whatever is attached to the hub, along with the modes of each device
+//
+//
+//
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use btleplug::api::BDAddr;
+
+use rust_powered_lego::{
+    connection_manager::ConnectionManager,
+    HubType,
+};
+
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let hub_mac_address = "90:84:2b:4e:5b:96";
+    let address = BDAddr::from_str(hub_mac_address)?;
+
+    let cm = ConnectionManager::new();
+    let hub = cm.get_hub(None, Some(address), 5).await?;
+
+    // The hub reports its devices right after connecting
+    let mut ports: Vec<u8> = hub.get_attached_devices().await?.keys().copied().collect();
+    ports.sort();
+
+    for port_id in ports {
+        println!("{}", hub.describe_port(port_id).await?);
+    }
+
+    Ok(())
+}
@@ -65,13 +65,11 @@ where
 }
 
 /// controller function gets the raw events from the event loop and decides what kind of command to execute
-/// See below as to how to create the hub itself.
-/// The hub itself is moved here because the motor has a reference to it. So, in order to avoid lifetime issues,
-/// both lives together.
+/// See below as to how to get the motor itself.
+/// The motor holds its own handle to the hub, so it can be moved here on its own.
 /// event_rx is the recieving end of the mpsc channel
-async fn controller(hub: impl HubType,  mut event_rx: UnboundedReceiver<Option<Event<'_, ()>>>)
+async fn controller(motor: impl MotorType,  mut event_rx: UnboundedReceiver<Option<Event<'_, ()>>>)
 {
-    let motor = hub.get_motor(TechnicHubPorts::A as u8).await.unwrap();
     let mut up_pressed = false;
     let mut down_pressed = false;
     loop {
@@ -157,9 +155,10 @@ fn main() -> Result<()> {
     runtime.block_on(async {
         let hub_mac_address = "90:84:2b:4e:5b:96";
         let hub = get_hub(hub_mac_address).await.unwrap();
+        let motor = hub.get_motor(TechnicHubPorts::A as u8).await.unwrap();
         // Seperating the controller (async function) from the event_loop (sync function)
         runtime.spawn(async move {
-            controller(hub, event_rx).await;
+            controller(motor, event_rx).await;
         });
     });
 
@@ -0,0 +1,172 @@
+// Calibration of a steering motor, between two physical end stops.
+// The end stops are detected by the motor stalling, so no fixed sleeps or travel distances are needed.
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+
+use crate::{
+    lego::{
+        message_parameters::StartupAndCompletionInfo,
+        consts::{
+            EndState,
+            Profile,
+        },
+    },
+    MotorType,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalibrationSettings {
+    pub speed:              i8,         // %, towards the end stops
+    pub max_power:          i8,         // %, low enough not to damage anything at the end stops
+    pub passes:             u8,         // The range is averaged over these
+    pub stall_time:         Duration,   // No movement for this long is a stall
+    pub stall_tolerance:    i32,        // Degrees that still count as no movement
+    pub timeout:            Duration,   // Giving up on finding an end stop, or on reaching the center, after this long
+}
+
+impl Default for CalibrationSettings {
+    fn default() -> Self {
+        Self {
+            speed:              10,
+            max_power:          15,
+            passes:             2,
+            stall_time:         Duration::from_millis(300),
+            stall_tolerance:    2,
+            timeout:            Duration::from_secs(10),
+        }
+    }
+}
+
+
+// All positions are in degrees.
+// min and max are the end stops, relative to the center.
+// center is the absolute position (see MotorType::absolute_position) of the center, so the calibration
+// can be saved and applied again later, e.g. after the hub was restarted.
+// It is None for motors without an absolute encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SteeringCalibration {
+    pub min:        i32,
+    pub max:        i32,
+    pub center:     Option<i16>,
+}
+
+impl SteeringCalibration {
+    pub fn range(&self) -> i32 {
+        self.max - self.min
+    }
+
+    // Keeps a target position between the end stops
+    pub fn clamp(&self, position: i32) -> i32 {
+        position.clamp(self.min, self.max)
+    }
+
+    // Presets the motor's position so that 0 is the center again.
+    // Within one turn of the motor, the absolute encoder tells where the center is without moving.
+    // Otherwise (or without an absolute encoder), the motor homes to the negative end stop first.
+    pub async fn apply<M: MotorType + Sync>(&self, motor: &M, settings: &CalibrationSettings) -> Result<()> {
+        let position = match self.center {
+            Some(center) if self.range() < 360 => {
+                let absolute = motor.absolute_position().await? as i32;
+                // Shortest way around the circle, -180 to 179
+                (absolute - center as i32 + 540).rem_euclid(360) - 180
+            },
+            _ => {
+                _ = run_to_end_stop(motor, -1, settings).await?;
+                self.min
+            },
+        };
+        _ = motor.set_abs_position(position, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await?;
+        Ok(())
+    }
+}
+
+
+// Measures the range between the end stops over settings.passes passes, and sets the center as 0.
+// The motor is left holding the center.
+pub async fn calibrate_steering<M: MotorType + Sync>(motor: &M, settings: &CalibrationSettings) -> Result<SteeringCalibration> {
+    if settings.passes == 0 {
+        bail!("[Error] At least one calibration pass is needed");
+    }
+    // Checked before moving anything, rather than failing after all the passes
+    let absolute = motor.has_absolute_encoder().await?;
+
+    let mut total = 0;
+    for _ in 0..settings.passes {
+        let max_end = run_to_end_stop(motor, 1, settings).await?;
+        let min_end = run_to_end_stop(motor, -1, settings).await?;
+        total += max_end - min_end;
+    }
+    let range = total / settings.passes as i32;
+
+    // The motor is at the negative end stop now
+    let min = -(range / 2);
+    _ = motor.set_abs_position(min, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await?;
+    motor.go_to_abs_position(
+        0,
+        settings.speed,
+        settings.max_power,
+        EndState::HOLD,
+        Profile::AccDec,
+        StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback,
+    ).await?.wait_for(settings.timeout).await?;
+
+    Ok(SteeringCalibration {
+        min,
+        max:    range + min,
+        center: if absolute { Some(motor.absolute_position().await?) } else { None },
+    })
+}
+
+
+/***************************************/
+/************* End Stops ***************/
+/***************************************/
+
+// Runs the motor in the direction of sign until it stalls, and returns the position there
+async fn run_to_end_stop<M: MotorType + Sync>(motor: &M, sign: i8, settings: &CalibrationSettings) -> Result<i32> {
+    let mut positions = motor.position_stream(1).await?;
+    // The hub sends the current position once notifications are on
+    let mut position = match time::timeout(settings.timeout, positions.next()).await {
+        Ok(Some(position)) => position.value,
+        _ => bail!("[Error] The motor didn't report its position"),
+    };
+
+    _ = motor.start_speed(
+        sign.signum() * settings.speed,
+        settings.max_power,
+        Profile::AccDec,
+        StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction,
+    ).await?;
+
+    let started = Instant::now();
+    // Where and when the motor was last seen moving
+    let mut anchor = (position, Instant::now());
+    let stalled = loop {
+        if started.elapsed() > settings.timeout {
+            break false;
+        }
+        match time::timeout(settings.stall_time, positions.next()).await {
+            Ok(Some(update)) => {
+                position = update.value;
+                if (position - anchor.0).abs() > settings.stall_tolerance {
+                    anchor = (position, Instant::now());
+                } else if anchor.1.elapsed() >= settings.stall_time {
+                    break true;
+                }
+            },
+            Ok(None) => break false,
+            // No new position at all
+            Err(_) => break true,
+        }
+    };
+
+    _ = motor.stop_motor(EndState::BRAKE, Profile::AccDec, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await?;
+    if !stalled {
+        bail!("[Error] No end stop found within {:?}", settings.timeout);
+    }
+    Ok(position)
+}
@@ -1,20 +1,103 @@
 use std::time::Duration;
-use std::str::FromStr;
-use std::collections::HashMap;
 
-use tokio::time;
+use num_traits::FromPrimitive;
+use tokio::{select, time};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
-use btleplug::api::{BDAddr, Manager as _, Central, ScanFilter, Peripheral as _};
+use btleplug::api::{BDAddr, Manager as _, Central, CentralEvent, PeripheralProperties, ScanFilter, Peripheral as _};
 use btleplug::platform::{Manager, Peripheral};
 
 use anyhow::{Result, anyhow};
 
 use crate::hub::Hub;
+use crate::lego::NotificationStream;
+use crate::lego::consts::{
+    HubKind,
+    LEGO_HUB_SERVICE_UUID,
+    LEGO_MANUFACTURER_ID,
+};
 
-struct PeripheralInfo {
-    address: BDAddr,
-    local_name: String,
-    // manufacturer_data: HashMap<u16, Vec<u8>>
+// The LEGO part of the advertisement: [button state, system type and device number, capabilities, last network, status, option]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManufacturerData {
+    pub button_pressed: bool,
+    pub system_type_id: u8,                 // System type and device number
+    pub kind:           Option<HubKind>,    // None for hubs this crate doesn't know
+    pub capabilities:   u8,                 // Bit mask - central role, peripheral role, LPF2 devices, remote controller
+    pub last_network:   u8,
+    pub status:         u8,
+}
+
+impl ManufacturerData {
+    // The data under LEGO_MANUFACTURER_ID, without the manufacturer id itself
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 5 {
+            return None;
+        }
+        Some(Self {
+            button_pressed: data[0] == 1,
+            system_type_id: data[1],
+            kind:           HubKind::from_u8(data[1]),
+            capabilities:   data[2],
+            last_network:   data[3],
+            status:         data[4],
+        })
+    }
+
+    pub(crate) fn from_properties(properties: &PeripheralProperties) -> Option<Self> {
+        properties.manufacturer_data
+            .get(&LEGO_MANUFACTURER_ID)
+            .and_then(|data| Self::parse(data))
+    }
+
+    // None unless the advertisement is a LEGO hub's - the hub service and the LEGO manufacturer data
+    pub fn from_advertisement(properties: &PeripheralProperties) -> Option<Self> {
+        if !properties.services.contains(&LEGO_HUB_SERVICE_UUID) {
+            return None;
+        }
+        Self::from_properties(properties)
+    }
+}
+
+
+// A hub seen by discover(), as advertised
+#[derive(Debug, Clone)]
+pub struct DiscoveredHub {
+    pub address:        BDAddr,
+    pub name:           String,
+    pub rssi:           Option<i16>,
+    pub hub_type:       Option<HubKind>,
+    pub button_pressed: bool,
+    peripheral:         Peripheral,
+}
+
+impl DiscoveredHub {
+    // None if the peripheral doesn't look like a LEGO hub
+    async fn from_peripheral(peripheral: Peripheral) -> Option<Self> {
+        let properties = peripheral.properties().await.ok()??;
+        let data = ManufacturerData::from_advertisement(&properties)?;
+        Some(Self {
+            address:        properties.address,
+            name:           properties.local_name.unwrap_or_else(|| String::from("(peripheral name unknown)")),
+            rssi:           properties.rssi,
+            hub_type:       data.kind,
+            button_pressed: data.button_pressed,
+            peripheral,
+        })
+    }
+
+    pub async fn connect(&self) -> Result<Hub> {
+        Hub::new(self.connect_peripheral().await?).await
+    }
+
+    async fn connect_peripheral(&self) -> Result<Peripheral> {
+        if !self.peripheral.is_connected().await? {
+            self.peripheral.connect().await?;
+        }
+        Ok(self.peripheral.clone())
+    }
 }
 
 pub struct ConnectionManager {}
@@ -34,91 +117,73 @@ impl ConnectionManager {
         Hub::new(p).await
     }
 
+    // Connects to the first hub advertising the given name or address.
+    // scan_time_seconds is how long to wait for it.
     async fn get_peripheral(
         &self, 
-        mut peripheral_name: Option<String>, 
-        mut bd_add: Option<BDAddr>,
+        peripheral_name: Option<String>, 
+        bd_add: Option<BDAddr>,
         scan_time_seconds: u64,
     ) -> Result<Peripheral> {
-        if bd_add.is_none() {
-            if let Ok(x) = BDAddr::from_str("00:00:00:00:00:00") {
-                bd_add = Some(x);
-            }
-        }
-        if peripheral_name.is_none() {
-            peripheral_name = Some(String::from_str("")?);
-        }
-        let peripherals = self.get_peripherals(scan_time_seconds).await?;
-        //println!("{}", peripherals.len());
-        let mut peripheral_info: PeripheralInfo;
-        if !peripherals.is_empty() {
-            
-            // All peripheral devices in range
-            for peripheral in peripherals.into_iter() {
-                peripheral_info = self.get_peripheral_info(&peripheral).await?;
-                // println!("{}", peripheral_info.local_name);
-                if peripheral_info.local_name.eq(peripheral_name.as_ref().unwrap()) || peripheral_info.address.eq(bd_add.as_ref().unwrap()) {
-                    let is_connected = peripheral.is_connected().await?;
-                    //while peripheral_info.manufacturer_data.is_empty() {
-                    //    peripheral_info = self.get_peripheral_info(&peripheral).await?;
-                    //}
-                    if !is_connected {
-                        println!("Connecting to peripheral {:?}...", &peripheral_info.local_name);
-                        if let Err(err) = peripheral.connect().await {
-                            eprintln!("Error connecting to peripheral, skipping: {}", err);
-                            continue;
-                        }
-                    }
-                    if peripheral.is_connected().await? {
-                        return Ok(peripheral);
-                    }
+        let mut hubs = self.discover().await?;
+        let search = async {
+            while let Some(hub) = hubs.next().await {
+                let name_matches = peripheral_name.as_deref() == Some(hub.name.as_str());
+                let address_matches = bd_add == Some(hub.address);
+                if !name_matches && !address_matches {
+                    continue;
+                }
+                match hub.connect_peripheral().await {
+                    Ok(peripheral) => return Ok(peripheral),
+                    Err(err) => eprintln!("Error connecting to peripheral, skipping: {}", err),
                 }
             }
-        }
-        Err(anyhow!("No connections found"))
+            Err(anyhow!("No connections found"))
+        };
+        time::timeout(Duration::from_secs(scan_time_seconds), search)
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("No connections found")))
     }
 
-    async fn get_peripherals(&self, scan_time_seconds: u64) -> Result<Vec<Peripheral>> {
+    // LEGO hubs as their advertisements arrive - the same hub shows up again with every advertisement.
+    // Scanning goes on until the stream is dropped.
+    pub async fn discover(&self) -> Result<NotificationStream<DiscoveredHub>> {
         let manager = Manager::new().await?;
         let adapter_list = manager.adapters().await?;
         if adapter_list.is_empty() {
-            eprintln!("No Bluetooth adapters found");
+            return Err(anyhow!("No Bluetooth adapters found"));
         }
 
-        let mut peripherals: Vec<Peripheral> = Vec::new();
-
-        for adapter in adapter_list.iter() {
-            //println!("Starting scan on {}...", adapter.adapter_info().await?);
-            adapter
-                .start_scan(ScanFilter::default())
-                .await
-                .expect("Can't scan BLE adapter for connected devices...");
-            time::sleep(Duration::from_secs(scan_time_seconds)).await;
-            peripherals.append(&mut adapter.peripherals().await?);
+        let (tx, rx) = mpsc::unbounded_channel();
+        for adapter in adapter_list.into_iter() {
+            let mut events = adapter.events().await?;
+            adapter.start_scan(ScanFilter { services: vec![LEGO_HUB_SERVICE_UUID] }).await?;
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    let id = select! {
+                        event = events.next() => match event {
+                            Some(CentralEvent::DeviceDiscovered(id)) |
+                            Some(CentralEvent::DeviceUpdated(id)) |
+                            Some(CentralEvent::ManufacturerDataAdvertisement { id, .. }) => id,
+                            Some(_) => continue,
+                            None => break,
+                        },
+                        _ = tx.closed() => break,
+                    };
+                    let hub = match adapter.peripheral(&id).await {
+                        Ok(peripheral) => DiscoveredHub::from_peripheral(peripheral).await,
+                        Err(_) => None,
+                    };
+                    if let Some(hub) = hub {
+                        if tx.send(hub).is_err() {
+                            break;
+                        }
+                    }
+                }
+                _ = adapter.stop_scan().await;
+            });
         }
-        Ok(peripherals)
-    }
-
-    async fn get_peripheral_info(&self, peripheral: &Peripheral) -> Result<PeripheralInfo> {
-        let properties = peripheral.properties().await?;
-        let def_str = String::from("(peripheral name unknown)");
-        let local_name = properties.as_ref().unwrap()
-            .local_name.as_ref()
-            .unwrap_or(&def_str);
-        let address = properties.as_ref()
-            .unwrap()
-            .address;
-        let mut manufacturer_data: HashMap<u16, Vec<u8>> = HashMap::new();
-        manufacturer_data.clone_from(&properties.as_ref()
-        .unwrap()
-        .manufacturer_data);
-        
-        Ok(PeripheralInfo { 
-            address: address, 
-            local_name: local_name.to_string(), 
-            // manufacturer_data: manufacturer_data,
-    })
-
+        Ok(Box::pin(UnboundedReceiverStream::new(rx)))
     }
 }
-
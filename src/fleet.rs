@@ -0,0 +1,167 @@
+// Several hubs at once - e.g. models with two or three Technic hubs.
+// The Fleet scans once, connects to the hubs concurrently and keeps them by their name or address.
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use btleplug::api::BDAddr;
+use tokio::time;
+use tokio_stream::StreamExt;
+
+use crate::{
+    connection_manager::{
+        ConnectionManager,
+        DiscoveredHub,
+    },
+    hub::Hub,
+    lego::{
+        message_parameters::StartupAndCompletionInfo,
+        consts::{
+            EndState,
+            Profile,
+        },
+    },
+    ports::MOTOR_TYPES,
+    HubType,
+    MotorType,
+};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HubId {
+    Name(String),
+    Address(BDAddr),
+}
+
+impl HubId {
+    fn matches(&self, hub: &DiscoveredHub) -> bool {
+        match self {
+            HubId::Name(name) => hub.name == *name,
+            HubId::Address(address) => hub.address == *address,
+        }
+    }
+}
+
+impl fmt::Display for HubId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HubId::Name(name) => write!(f, "{}", name),
+            HubId::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+
+#[derive(Default)]
+pub struct Fleet {
+    discovered:     Vec<DiscoveredHub>,     // Found by the scan, the latest advertisement of each
+    hubs:           HashMap<HubId, Hub>,
+}
+
+impl Fleet {
+    // An empty fleet, without scanning. Hubs can still be added with insert().
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Collects the hubs advertising for scan_time_seconds (see ConnectionManager::discover).
+    // connect() picks the hubs out of what was found.
+    pub async fn scan(scan_time_seconds: u64) -> Result<Self> {
+        let mut advertisements = ConnectionManager::new().discover().await?;
+        let mut discovered: Vec<DiscoveredHub> = Vec::new();
+        let collect = async {
+            while let Some(hub) = advertisements.next().await {
+                match discovered.iter_mut().find(|known| known.address == hub.address) {
+                    Some(known) => *known = hub,
+                    None => discovered.push(hub),
+                }
+            }
+        };
+        // Scanning stops with the stream
+        _ = time::timeout(Duration::from_secs(scan_time_seconds), collect).await;
+        Ok(Self {
+            discovered,
+            hubs: HashMap::new(),
+        })
+    }
+
+    // Connects to all the given hubs concurrently. Fails if any of them wasn't found or couldn't connect,
+    // the ones that did connect are kept anyway.
+    pub async fn connect(&mut self, ids: &[HubId]) -> Result<()> {
+        let mut failed = Vec::new();
+        let mut connections = Vec::new();
+        for id in ids.iter() {
+            if self.hubs.contains_key(id) {
+                continue;
+            }
+            match self.discovered.iter().find(|hub| id.matches(hub)) {
+                Some(hub) => {
+                    let hub = hub.clone();
+                    connections.push((id.clone(), tokio::spawn(async move { hub.connect().await })));
+                },
+                None => failed.push(format!("{}: not found by the scan", id)),
+            }
+        }
+
+        // Every connection is awaited, so none is left running unseen
+        for (id, connection) in connections {
+            match connection.await.map_err(|err| anyhow!(err)).and_then(|hub| hub) {
+                Ok(hub) => { self.hubs.insert(id, hub); },
+                Err(err) => failed.push(format!("{}: {}", id, err)),
+            }
+        }
+        if !failed.is_empty() {
+            bail!("[Error] Couldn't connect to {}", failed.join(", "));
+        }
+        Ok(())
+    }
+
+    // Adds an already connected hub
+    pub fn insert(&mut self, id: HubId, hub: Hub) {
+        self.hubs.insert(id, hub);
+    }
+
+    pub fn remove(&mut self, id: &HubId) -> Option<Hub> {
+        self.hubs.remove(id)
+    }
+
+    pub fn hub(&self, id: &HubId) -> Option<&Hub> {
+        self.hubs.get(id)
+    }
+
+    pub fn hubs(&self) -> &HashMap<HubId, Hub> {
+        &self.hubs
+    }
+
+    // Brakes every motor of every hub. All of them are tried, even if some fail.
+    pub async fn stop_all(&self) -> Result<()> {
+        let mut failed = Vec::new();
+        for (id, hub) in self.hubs.iter() {
+            if let Err(err) = stop_motors(hub).await {
+                failed.push(format!("{}: {}", id, err));
+            }
+        }
+        if !failed.is_empty() {
+            bail!("[Error] Couldn't stop all motors - {}", failed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+async fn stop_motors(hub: &Hub) -> Result<()> {
+    let mut motor_ports: Vec<u8> = hub.get_attached_devices().await?
+        .values()
+        .filter(|device| device.io_type.is_some_and(|io_type| MOTOR_TYPES.contains(&io_type)))
+        .map(|device| device.port_id)
+        .collect();
+    motor_ports.sort();
+    for port_id in motor_ports {
+        _ = hub.get_motor(port_id).await?.stop_motor(
+            EndState::BRAKE,
+            Profile::AccDec,
+            StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction,
+        ).await?;
+    }
+    Ok(())
+}
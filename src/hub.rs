@@ -1,60 +1,425 @@
 use core::result::Result::Ok;
-use std::pin::Pin;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 use async_trait::async_trait;
-use btleplug::api::ValueNotification;
-use num_traits::FromPrimitive;
+use tokio::time;
+use tokio_stream::StreamExt;
 
 //use anyhow::Ok;
 use anyhow::bail;
+use btleplug::api::Peripheral as _;
 use btleplug::platform::Peripheral;
 
 use anyhow::Result;
 use byteorder::ByteOrder;
 use byteorder::LittleEndian;
-use tokio_stream::Stream;
 
-use crate::HubType;
+use crate::{
+    HubType,
+    HubPropertiesType,
+};
+use crate::connection_manager::ManufacturerData;
 use crate::lego::{
+    BtleTransport,
+    CommandHandle,
     Communicator,
+    ConnectionEvent,
+    ExpectedReply,
     MessageTypes,
+    NotificationStream,
+    ReconnectPolicy,
+    Transport,
+    UpstreamMessage,
 };
 use crate::lego::{
     message_parameters:: {
         HubActionsParams,
         HubActionsTypes,
+        HubPropertiesOperations,
+        HubPropertiesParams,
+        HubPropertiesProperties,
+        CombinedModeSubcommand,
         PortInformationType,
         PortInformationRequestParams,
         PortModeInformationType,
         PortModeInformationRequestParams,
+        PortInputFormatSetupCombinedModeParams,
         PortInputFormatSetupSingleParams,
-        PortOutputCommandParams
+        PortOutputCommandParams,
+        VirtualPortSetupParams,
+    },
+    attached_io::{
+        AttachedDevice,
+        AttachmentEvent,
     },
     consts::{
-        PortType,
+        HubKind,
+        HubPorts,
         PortInfoModeReplyCapabilities,
+        PortType,
     },
+    port_modes::{
+        CombinedModeDecoder,
+        CombinedModeSetup,
+        Mapping,
+        ModeDescription,
+        ModeSet,
+        PortDescription,
+        PortValue,
+        ValueFormat,
+        ValueRange,
+        parse_mode_string,
+    },
+    upstream_messages::{
+        GenericErrorMessage,
+        HubPropertyValue,
+        PortInformationMessage,
+        PortInformationPayload,
+        PortInputFormatSingleMessage,
+        PortOutputFeedback,
+        PortValueCombinedModeMessage,
+        PortValueSingleMessage,
+    },
+};
+use crate::ports::{
+    Accelerometer,
+    ColorDistanceSensor,
+    CurrentSensor,
+    Gyro,
+    Led,
+    Motor,
+    SyncedMotorPair,
+    TechnicColorSensor,
+    TechnicDistanceSensor,
+    TechnicForceSensor,
+    TiltSensor,
+    VoltageSensor,
+    MOTOR_TYPES,
 };
-use crate::ports::Motor;
 
+const MAX_ADVERTISING_NAME_LENGTH: usize = 14;
+
+// Cheap to clone - all clones share the same connection, so each device can own one
+#[derive(Clone)]
 pub struct Hub {
-    communicator: Communicator,
+    communicator:       Arc<Communicator<Box<dyn Transport>>>,
+    mode_descriptions:  Arc<Mutex<HashMap<(u16, u8), ModeDescription>>>,   // (io type id, mode id) -> description
+    reconnect_policy:   Arc<Mutex<Option<ReconnectPolicy>>>,
+    kind:               Option<HubKind>,    // As advertised
+}
+
+// Held by the connection supervisor, so it doesn't keep the hub alive
+struct WeakHub {
+    communicator:       Weak<Communicator<Box<dyn Transport>>>,
+    mode_descriptions:  Weak<Mutex<HashMap<(u16, u8), ModeDescription>>>,
+    reconnect_policy:   Weak<Mutex<Option<ReconnectPolicy>>>,
+    kind:               Option<HubKind>,
+}
+
+impl WeakHub {
+    fn upgrade(&self) -> Option<Hub> {
+        Some(Hub {
+            communicator:       self.communicator.upgrade()?,
+            mode_descriptions:  self.mode_descriptions.upgrade()?,
+            reconnect_policy:   self.reconnect_policy.upgrade()?,
+            kind:               self.kind,
+        })
+    }
 }
 
 impl Hub {
     pub async fn new(p: Peripheral) -> Result<Self> {
-        let communicator = Communicator::new(p).await?;
-        Ok(Self { communicator })
+        // The advertisement tells what kind of hub it is
+        let kind = p.properties().await?
+            .and_then(|properties| ManufacturerData::from_properties(&properties))
+            .and_then(|data| data.kind);
+        let transport = BtleTransport::new(p).await?;
+        Self::with_transport_of_kind(transport, kind).await
+    }
+
+    // Any Transport will do. See lego::InMemoryTransport for a hub-less one.
+    pub async fn with_transport<T: Transport + 'static>(transport: T) -> Result<Self> {
+        Self::with_transport_of_kind(transport, None).await
+    }
+
+    // Like with_transport, for simulating a specific hub
+    pub async fn with_transport_of_kind<T: Transport + 'static>(transport: T, kind: Option<HubKind>) -> Result<Self> {
+        let communicator = Communicator::new(Box::new(transport) as Box<dyn Transport>).await?;
+        let hub = Self {
+            communicator:       Arc::new(communicator),
+            mode_descriptions:  Arc::new(Mutex::new(HashMap::new())),
+            reconnect_policy:   Arc::new(Mutex::new(None)),
+            kind,
+        };
+        tokio::spawn(supervise_connection(hub.downgrade(), hub.communicator.dispatcher().connection_stream()));
+        Ok(hub)
+    }
+
+    // Time to wait for the hub to reply before giving up with ReplyTimeoutError.
+    // Applies to all clones of the hub.
+    pub fn set_reply_timeout(&self, reply_timeout: Duration) {
+        self.communicator.set_reply_timeout(reply_timeout);
+    }
+
+    // Without a policy (the default) a dropped link stays down.
+    // Applies to all clones of the hub.
+    pub fn set_reconnect_policy(&self, reconnect_policy: Option<ReconnectPolicy>) {
+        *self.reconnect_policy.lock().unwrap() = reconnect_policy;
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.communicator.dispatcher().is_connected()
+    }
+
+    // None when the advertisement wasn't seen, or the hub is unknown to this crate
+    pub fn kind(&self) -> Option<HubKind> {
+        self.kind
+    }
+
+    // The port of a built-in device, as the hub's kind has it
+    fn built_in_port(&self, port: fn(&HubPorts) -> Option<u8>, name: &str) -> Result<u8> {
+        let kind = match self.kind {
+            Some(kind) => kind,
+            None => bail!("[Error] The hub's kind is unknown, so is the port of its {}", name),
+        };
+        match kind.ports().and_then(|ports| port(&ports)) {
+            Some(port_id) => Ok(port_id),
+            None => bail!("[Error] The {:?} has no built-in {} known to this crate", kind, name),
+        }
+    }
+
+    fn downgrade(&self) -> WeakHub {
+        WeakHub {
+            communicator:       Arc::downgrade(&self.communicator),
+            mode_descriptions:  Arc::downgrade(&self.mode_descriptions),
+            reconnect_policy:   Arc::downgrade(&self.reconnect_policy),
+            kind:               self.kind,
+        }
+    }
+
+    // Tries to get the link back according to the policy, then sets the ports up as they were
+    async fn reconnect(&self) {
+        let policy = match *self.reconnect_policy.lock().unwrap() {
+            Some(policy) => policy,
+            None => return,
+        };
+        let dispatcher = self.communicator.dispatcher();
+        // The hub forgets these along with the connection
+        let input_formats = dispatcher.input_formats();
+        let combined_modes = dispatcher.combined_modes();
+        let virtual_ports = dispatcher.take_virtual_ports();
+
+        for attempt in 1..=policy.max_attempts {
+            dispatcher.send_connection_event(ConnectionEvent::Reconnecting(attempt));
+            time::sleep(policy.delay(attempt)).await;
+            if self.communicator.reconnect().await.is_err() {
+                continue;
+            }
+            for port_id in self.restore(&input_formats, &combined_modes, &virtual_ports).await {
+                dispatcher.send_connection_event(ConnectionEvent::RestoreFailed(port_id));
+            }
+            dispatcher.send_connection_event(ConnectionEvent::Connected);
+            return;
+        }
+        dispatcher.send_connection_event(ConnectionEvent::GaveUp);
+    }
+
+    // Sets up again whatever the hub forgot. Each part is tried, and the ports which failed are returned.
+    async fn restore(
+        &self,
+        input_formats: &HashMap<u8, PortInputFormatSingleMessage>,
+        combined_modes: &[CombinedModeSetup],
+        virtual_ports: &[AttachedDevice],
+    ) -> Vec<u8> {
+        let mut failed = Vec::new();
+        // In their original order, so they are likely to get their original ids back
+        for device in virtual_ports.iter() {
+            if let Some((port_a, port_b)) = device.virtual_members {
+                match self.create_virtual_port(port_a, port_b).await {
+                    Ok(port_id) if port_id != device.port_id => {
+                        self.communicator.dispatcher().send_connection_event(ConnectionEvent::VirtualPortMoved(device.port_id, port_id));
+                    },
+                    Ok(_) => (),
+                    Err(_) => failed.push(device.port_id),
+                }
+            }
+        }
+        let mut formats: Vec<&PortInputFormatSingleMessage> = input_formats.values().collect();
+        formats.sort_by_key(|format| format.port_id);
+        for format in formats {
+            if self.setup_port_input_format(format.port_id, format.mode_id, format.delta, format.notifications_enabled).await.is_err() {
+                failed.push(format.port_id);
+            }
+        }
+        // Value streams of these go on with the decoders they have, the setup being the same
+        for setup in combined_modes.iter() {
+            if self.setup_port_combined_mode(setup.clone()).await.is_err() {
+                failed.push(setup.port_id);
+            }
+        }
+        failed
     }
 
     async fn get_port_info(&self, port_id: u8, information_type: PortInformationType) -> Result<Vec<u8>> {
-        self.communicator.send_message(
+        let reply_type = match information_type {
+            PortInformationType::PortValue => MessageTypes::PortValueSingle,
+            _ => MessageTypes::PortInformation,
+        };
+        self.communicator.request(
             MessageTypes::PortInformationRequest,
             PortInformationRequestParams {
                 port_id: port_id as u8,
                 information_type: information_type,
+            },
+            ExpectedReply::new(reply_type, Some(port_id)),
+        ).await
+    }
+
+    async fn fetch_mode_description(&self, port_id: u8, mode_id: u8, is_input: bool, is_output: bool) -> Result<ModeDescription> {
+        let info = |info_type| self.get_mode_information(port_id, mode_id, info_type);
+        Ok(ModeDescription {
+            mode_id,
+            is_input,
+            is_output,
+            name:               parse_mode_string(&info(PortModeInformationType::Name).await?),
+            raw:                ValueRange::parse(&info(PortModeInformationType::Raw).await?)?,
+            pct:                ValueRange::parse(&info(PortModeInformationType::Pct).await?)?,
+            si:                 ValueRange::parse(&info(PortModeInformationType::Si).await?)?,
+            symbol:             parse_mode_string(&info(PortModeInformationType::Symbol).await?),
+            mapping:            Mapping::parse(&info(PortModeInformationType::Mapping).await?)?,
+            // Older firmwares reply to these two with an error
+            motor_bias:         info(PortModeInformationType::MotorBias).await.ok()
+                                    .and_then(|payload| payload.first().copied()),
+            capability_bits:    info(PortModeInformationType::CapabilityBits).await.ok()
+                                    .and_then(|payload| payload.get(..6).map(|bits| bits.try_into().unwrap())),
+            value_format:       ValueFormat::parse(&info(PortModeInformationType::ValueFormat).await?)?,
+        })
+    }
+
+    // Descriptions are the same for every device of a type, so these are kept per io type
+    fn cache_mode_description(&self, port_id: u8, description: &ModeDescription) {
+        if let Some(device) = self.communicator.dispatcher().port_table().get(port_id) {
+            self.mode_descriptions.lock().unwrap().insert((device.io_type_id, description.mode_id), description.clone());
+        }
+    }
+
+    // The device on the port, if it is one of the given types
+    pub(crate) async fn expect_device(&self, port_id: u8, io_types: &[PortType], kind: &str) -> Result<AttachedDevice> {
+        let device = self.get_attached_device(port_id).await?;
+        match device.io_type {
+            Some(io_type) if io_types.contains(&io_type) => Ok(device),
+            _ => bail!("[Error] The device on port {} is not a {} (io type {})", port_id, kind, device.io_type_id),
+        }
+    }
+
+    // Switches the port to the mode, unless it is there already - notifications are left as they are then.
+    // Writing direct mode data works only in the port's current mode.
+    pub(crate) async fn ensure_input_mode(&self, port_id: u8, mode_id: u8) -> Result<()> {
+        match self.communicator.dispatcher().input_format(port_id) {
+            Some(format) if format.mode_id == mode_id => Ok(()),
+            _ => self.setup_port_input_format(port_id, mode_id, 1, false).await,
+        }
+    }
+
+    // ensure_input_mode for one-off reads. Switching a port someone streams from would silence their stream,
+    // so while a stream is held the port has to be in the mode already, and not in a combined mode.
+    pub(crate) async fn ensure_read_mode(&self, port_id: u8, mode_id: u8) -> Result<()> {
+        let dispatcher = self.communicator.dispatcher();
+        if dispatcher.is_streaming(port_id) {
+            if dispatcher.combined_modes().iter().any(|setup| setup.port_id == port_id) {
+                bail!("[Error] Port {} is streaming in a combined mode - reading mode {} would stop the stream", port_id, mode_id);
+            }
+            if let Some(format) = dispatcher.input_format(port_id) {
+                if format.mode_id != mode_id {
+                    bail!("[Error] Port {} is streaming mode {} - reading mode {} would stop the stream", port_id, format.mode_id, mode_id);
+                }
+            }
+        }
+        self.ensure_input_mode(port_id, mode_id).await
+    }
+
+    // setup_port_combined_mode, for callers which have fetched the port's combinations already
+    pub(crate) async fn setup_combined_mode(&self, setup: CombinedModeSetup, combinations: &[ModeSet]) -> Result<CombinedModeDecoder> {
+        let port_id = setup.port_id;
+        if setup.mode_datasets.is_empty() || setup.mode_datasets.len() > 16 {
+            bail!("[Error] A combined mode has to have 1 to 16 mode/datasets")
+        }
+        let mode_set = setup.mode_set()?;
+        match combinations.get(setup.combination_index as usize) {
+            Some(combination) if combination.includes(mode_set) => (),
+            _ => bail!("[Error] Modes {:?} are not combination {} of port {}", mode_set.modes(), setup.combination_index, port_id),
+        }
+
+        // The value format of each mode tells how to split the values later on
+        let mut formats: HashMap<u8, ValueFormat> = HashMap::new();
+        for (mode, _) in setup.modes.iter() {
+            let payload = self.get_mode_information(port_id, *mode, PortModeInformationType::ValueFormat).await?;
+            formats.insert(*mode, ValueFormat::parse(&payload)?);
+        }
+        let mut entries = Vec::new();
+        for mode_dataset in setup.mode_datasets.iter() {
+            let format = formats[&mode_dataset.mode];
+            if mode_dataset.dataset >= format.datasets {
+                bail!("[Error] Mode {} of port {} has only {} datasets", mode_dataset.mode, port_id, format.datasets)
             }
+            entries.push((*mode_dataset, format.dataset_type));
+        }
+
+        self.communicator.send_message(
+            MessageTypes::PortInputFormatSetupCombinedMode,
+            PortInputFormatSetupCombinedModeParams {
+                port_id,
+                subcommand: CombinedModeSubcommand::LockForSetup,
+            },
         ).await?;
-        self.communicator.read_message().await
+        let locked_setup = async {
+            for (mode, delta) in setup.modes.iter() {
+                self.setup_port_input_format(port_id, *mode, *delta, true).await?;
+            }
+            self.communicator.send_message(
+                MessageTypes::PortInputFormatSetupCombinedMode,
+                PortInputFormatSetupCombinedModeParams {
+                    port_id,
+                    subcommand: CombinedModeSubcommand::SetModeAndDataSetCombination {
+                        combination_index:  setup.combination_index,
+                        mode_datasets:      setup.mode_datasets.iter().map(|x| (x.mode, x.dataset)).collect(),
+                    },
+                },
+            ).await?;
+            // The hub confirms the whole setup with PortInputFormatCombinedMode
+            self.communicator.request(
+                MessageTypes::PortInputFormatSetupCombinedMode,
+                PortInputFormatSetupCombinedModeParams {
+                    port_id,
+                    subcommand: CombinedModeSubcommand::UnlockAndStartMultiUpdateEnabled,
+                },
+                ExpectedReply::new(MessageTypes::PortInputFormatCombinedMode, Some(port_id)),
+            ).await
+        };
+        if let Err(err) = locked_setup.await {
+            // Otherwise the port stays locked for setup
+            _ = self.communicator.send_message(
+                MessageTypes::PortInputFormatSetupCombinedMode,
+                PortInputFormatSetupCombinedModeParams {
+                    port_id,
+                    subcommand: CombinedModeSubcommand::UnlockAndStartMultiUpdateDisabled,
+                },
+            ).await;
+            return Err(err);
+        }
+        // To be set up again after reconnecting
+        self.communicator.dispatcher().record_combined_mode(setup);
+
+        Ok(CombinedModeDecoder { port_id, entries })
+    }
+
+    // The mode a port reports its values in
+    fn current_mode(&self, port_id: u8) -> Result<u8> {
+        match self.communicator.dispatcher().input_format(port_id) {
+            Some(format) => Ok(format.mode_id),
+            None => bail!("[Error] The mode of port {} is unknown. Set it with setup_port_input_format first.", port_id),
+        }
     }
 }
 
@@ -70,9 +435,59 @@ impl HubType for Hub {
         ).await
     }
 
-    async fn get_notification(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
-        //self.communicator.subscribe_for_notifications().await?;
-        Ok(self.communicator.get_notification_stream().await?)
+    async fn get_notification(&self) -> Result<NotificationStream<UpstreamMessage>> {
+        Ok(self.communicator.dispatcher().message_stream())
+    }
+
+    async fn get_port_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValueSingleMessage>> {
+        Ok(self.communicator.dispatcher().port_value_stream(port_id))
+    }
+
+    async fn get_combined_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValueCombinedModeMessage>> {
+        Ok(self.communicator.dispatcher().combined_value_stream(port_id))
+    }
+
+    async fn get_attachment_stream(&self) -> Result<NotificationStream<AttachmentEvent>> {
+        Ok(self.communicator.dispatcher().attachment_stream())
+    }
+
+    async fn get_attached_devices(&self) -> Result<HashMap<u8, AttachedDevice>> {
+        Ok(self.communicator.dispatcher().port_table().devices().clone())
+    }
+
+    async fn get_attached_device(&self, port_id: u8) -> Result<AttachedDevice> {
+        let dispatcher = self.communicator.dispatcher();
+        // Subscribing before looking at the table, so an attachment in between won't be missed
+        let mut attachments = dispatcher.attachment_stream();
+        if let Some(device) = dispatcher.port_table().get(port_id) {
+            return Ok(device.clone());
+        }
+        let wait_for_device = async {
+            while let Some(event) = attachments.next().await {
+                if let AttachmentEvent::Attached(device) = event {
+                    if device.port_id == port_id {
+                        return Some(device);
+                    }
+                }
+            }
+            None
+        };
+        match time::timeout(self.communicator.reply_timeout(), wait_for_device).await {
+            Ok(Some(device)) => Ok(device),
+            _ => bail!("[Error] Nothing is attached to port {}", port_id),
+        }
+    }
+
+    async fn get_feedback_stream(&self) -> Result<NotificationStream<PortOutputFeedback>> {
+        Ok(self.communicator.dispatcher().feedback_stream())
+    }
+
+    async fn get_error_stream(&self) -> Result<NotificationStream<GenericErrorMessage>> {
+        Ok(self.communicator.dispatcher().error_stream())
+    }
+
+    async fn get_connection_event_stream(&self) -> Result<NotificationStream<ConnectionEvent>> {
+        Ok(self.communicator.dispatcher().connection_stream())
     }
 
     async fn get_port_info_value(
@@ -82,22 +497,52 @@ impl HubType for Hub {
         let msg = self.get_port_info(
             port_id, 
             PortInformationType::PortValue).await?;
-        Ok(PortInfoValueReply {port_type: FromPrimitive::from_u8(msg[5])})
+        match UpstreamMessage::parse(&msg)? {
+            UpstreamMessage::PortValueSingle(reply) => Ok(PortInfoValueReply {
+                port_id:    reply.port_id,
+                data:       reply.data,
+            }),
+            other => bail!("[Error] Unexpected reply to port value request: {:?}", other.message_type()),
+        }
     }
 
     async fn get_port_info_raw_value(
         &self,
         port_id: u8
     ) -> Result<i32> {
-        let msg = self.get_port_info(port_id, PortInformationType::PortValue).await?;
-        match msg[0] {
-            0x05 => Ok(i32::from_u8(msg[4]).unwrap()),
-            0x06 => Ok(i32::from_u16(u16::from_le_bytes(msg[4..6].try_into().unwrap())).unwrap()),
-            0x08 => Ok(i32::from_le_bytes(msg[4..8].try_into().unwrap())),
+        if self.communicator.dispatcher().input_format(port_id).is_some() {
+            let value = self.get_port_value(port_id).await?;
+            return match value.raw.first() {
+                Some(raw) => Ok(*raw),
+                None => bail!("[Error] Port {} sent an empty value", port_id),
+            };
+        }
+        // The mode is unknown, so is the format. Taking it as a single signed value.
+        let reply = self.get_port_info_value(port_id).await?;
+        let data = reply.data.as_slice();
+        match data.len() {
+            1 => Ok(i32::from(data[0] as i8)),
+            2 => Ok(i32::from(LittleEndian::read_i16(data))),
+            4 => Ok(LittleEndian::read_i32(data)),
             _ => bail!("Such port value reply is not currently supported.")
         }
     }
 
+    async fn get_port_value(&self, port_id: u8) -> Result<PortValue> {
+        let description = self.describe_mode(port_id, self.current_mode(port_id)?).await?;
+        let reply = self.get_port_info_value(port_id).await?;
+        description.decode(port_id, &reply.data)
+    }
+
+    async fn get_decoded_port_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValue>> {
+        let description = self.describe_mode(port_id, self.current_mode(port_id)?).await?;
+        Ok(Box::pin(
+            self.communicator.dispatcher()
+                .port_value_stream(port_id)
+                .filter_map(move |msg| description.decode(msg.port_id, &msg.data).ok())
+        ))
+    }
+
     async fn get_port_info_mode(
         &self, 
         port_id: u8,
@@ -105,14 +550,25 @@ impl HubType for Hub {
         let msg = self.get_port_info(
             port_id, 
             PortInformationType::ModeInfo).await?;
-        Ok(PortInfoModeReply {
-            port_id:            msg[3].clone(), 
-            info_type:          msg[4].clone(), 
-            capabilities:       parse_capabilities(msg[5]), 
-            total_mode_count:   msg[6].clone(), 
-            input_modes:        parse_io_modes(LittleEndian::read_u16(&msg[7..9])), 
-            output_modes:       parse_io_modes(LittleEndian::read_u16(&msg[9..11])),
-        })
+        match UpstreamMessage::parse(&msg)? {
+            UpstreamMessage::PortInformation(PortInformationMessage {
+                port_id,
+                info: PortInformationPayload::ModeInfo {
+                    capabilities,
+                    total_mode_count,
+                    input_modes,
+                    output_modes,
+                },
+            }) => Ok(PortInfoModeReply {
+                port_id, 
+                info_type:          PortInformationType::ModeInfo as u8, 
+                capabilities:       parse_capabilities(capabilities), 
+                total_mode_count, 
+                input_modes:        parse_io_modes(input_modes), 
+                output_modes:       parse_io_modes(output_modes),
+            }),
+            other => bail!("[Error] Unexpected reply to mode info request: {:?}", other.message_type()),
+        }
     }
 
     async fn get_port_info_combinations(
@@ -122,7 +578,21 @@ impl HubType for Hub {
         let msg = self.get_port_info(
             port_id, 
             PortInformationType::PossibleModeCombinations).await?;
-        Ok(PortInfoCombinationsReply {data: msg})
+        match UpstreamMessage::parse(&msg)? {
+            UpstreamMessage::PortInformation(PortInformationMessage {
+                port_id,
+                info: PortInformationPayload::PossibleModeCombinations { combinations },
+            }) => Ok(PortInfoCombinationsReply {
+                port_id,
+                // The list is terminated by an empty combination
+                combinations: combinations
+                    .into_iter()
+                    .take_while(|combination| *combination != 0)
+                    .map(ModeSet)
+                    .collect(),
+            }),
+            other => bail!("[Error] Unexpected reply to mode combinations request: {:?}", other.message_type()),
+        }
     }    
 
     async fn get_mode_information(
@@ -132,15 +602,63 @@ impl HubType for Hub {
         info_type: PortModeInformationType
     ) -> Result<Vec<u8>>
     {
-        self.communicator.send_message(
+        let msg = self.communicator.request(
             MessageTypes::PortModeInformationRequest,
             PortModeInformationRequestParams {
                 port_id: port_id as u8,
                 mode_id: mode_id,
                 information_type: info_type,
+            },
+            ExpectedReply::new(MessageTypes::PortModeInformation, Some(port_id)),
+        ).await?;
+        match UpstreamMessage::parse(&msg)? {
+            UpstreamMessage::PortModeInformation(reply) => Ok(reply.payload),
+            other => bail!("[Error] Unexpected reply to mode information request: {:?}", other.message_type()),
+        }
+    }
+
+    async fn describe_port(&self, port_id: u8) -> Result<PortDescription> {
+        let mode_info = self.get_port_info_mode(port_id).await?;
+        let combinations = if mode_info.capabilities.contains(&PortInfoModeReplyCapabilities::LogicalCombinable) {
+            self.get_port_info_combinations(port_id).await?.combinations
+        } else {
+            Vec::new()
+        };
+        let mut modes = Vec::new();
+        for mode_id in 0..mode_info.total_mode_count {
+            let mode = self.fetch_mode_description(
+                port_id,
+                mode_id,
+                mode_info.input_modes.contains(&mode_id),
+                mode_info.output_modes.contains(&mode_id),
+            ).await?;
+            self.cache_mode_description(port_id, &mode);
+            modes.push(mode);
+        }
+        Ok(PortDescription {
+            port_id,
+            device:         self.communicator.dispatcher().port_table().get(port_id).cloned(),
+            capabilities:   mode_info.capabilities,
+            modes,
+            combinations,
+        })
+    }
+
+    async fn describe_mode(&self, port_id: u8, mode_id: u8) -> Result<ModeDescription> {
+        if let Some(device) = self.communicator.dispatcher().port_table().get(port_id) {
+            if let Some(description) = self.mode_descriptions.lock().unwrap().get(&(device.io_type_id, mode_id)) {
+                return Ok(description.clone());
             }
+        }
+        let mode_info = self.get_port_info_mode(port_id).await?;
+        let description = self.fetch_mode_description(
+            port_id,
+            mode_id,
+            mode_info.input_modes.contains(&mode_id),
+            mode_info.output_modes.contains(&mode_id),
         ).await?;
-        self.communicator.read_message().await
+        self.cache_mode_description(port_id, &description);
+        Ok(description)
     }
 
     async fn setup_port_input_format(
@@ -150,49 +668,330 @@ impl HubType for Hub {
         delta:                  u32,
         enable_notifications:   bool,
     ) -> Result<()> {
-        self.communicator.send_message(
+        _ = self.communicator.request(
             MessageTypes::PortInputFormatSetupSingle,
             PortInputFormatSetupSingleParams {
                 port_id:                port_id,
                 mode_id:                mode_id,
                 delta:                  delta,
                 enable_notifications:   enable_notifications,
-            }
+            },
+            ExpectedReply::new(MessageTypes::PortInputFormatSingle, Some(port_id)),
         ).await?;
-        _ = self.communicator.read_message().await?;
         Ok(())
     }
 
-    async fn send_output_command(&self, subcommand: PortOutputCommandParams)-> Result<Vec<u8>> {
+    async fn setup_port_combined_mode(&self, setup: CombinedModeSetup) -> Result<CombinedModeDecoder> {
+        let combinations = self.get_port_info_combinations(setup.port_id).await?.combinations;
+        self.setup_combined_mode(setup, &combinations).await
+    }
+
+    async fn send_output_command(&self, subcommand: PortOutputCommandParams)-> Result<CommandHandle> {
+        let port_id = subcommand.port_id;
+        // The hub replies only when feedback was asked for
+        if !subcommand.start_up_info.feedback_requested() {
+            self.communicator.send_message(
+                MessageTypes::PortOutputCommand,
+                subcommand
+            ).await?;
+            return Ok(CommandHandle::without_feedback(port_id));
+        }
+        let dispatcher = self.communicator.dispatcher();
+        let previous_pending = dispatcher.start_command(port_id);
+        let handle = CommandHandle::new(port_id, dispatcher.message_stream(), previous_pending, self.communicator.reply_timeout());
         self.communicator.send_message(
             MessageTypes::PortOutputCommand,
             subcommand
         ).await?;
-        self.communicator.read_message().await
+        Ok(handle)
     }
 
     async fn get_motor(&self, port_id: u8) -> Result<Motor> {
-        // (TODO) Make sure this is really a motor!!
+        _ = self.expect_device(port_id, &MOTOR_TYPES, "motor").await?;
         Ok(Motor {
-            hub: self,
+            hub: self.clone(),
             port_id: port_id as u8
         })
     }
 
+    async fn get_led(&self, port_id: u8) -> Result<Led> {
+        _ = self.expect_device(port_id, &[PortType::HubLed], "LED").await?;
+        Ok(Led {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_accelerometer(&self, port_id: u8) -> Result<Accelerometer> {
+        _ = self.expect_device(port_id, &[PortType::TechnicMediumHubAccelerometer], "accelerometer").await?;
+        Ok(Accelerometer {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_gyro(&self, port_id: u8) -> Result<Gyro> {
+        _ = self.expect_device(port_id, &[PortType::TechnicMediumHubGyroSensor], "gyro").await?;
+        Ok(Gyro {
+            hub: self.clone(),
+            port_id,
+        })
+    }
 
+    async fn get_tilt_sensor(&self, port_id: u8) -> Result<TiltSensor> {
+        _ = self.expect_device(port_id, &[PortType::TechnicMediumHubTiltSensor], "tilt sensor").await?;
+        Ok(TiltSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
 
+    async fn get_voltage_sensor(&self, port_id: u8) -> Result<VoltageSensor> {
+        _ = self.expect_device(port_id, &[PortType::VoltageSensor], "voltage sensor").await?;
+        Ok(VoltageSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_current_sensor(&self, port_id: u8) -> Result<CurrentSensor> {
+        _ = self.expect_device(port_id, &[PortType::CurrentSensor], "current sensor").await?;
+        Ok(CurrentSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_hub_led(&self) -> Result<Led> {
+        self.get_led(self.built_in_port(|ports| ports.led, "LED")?).await
+    }
+
+    async fn get_hub_accelerometer(&self) -> Result<Accelerometer> {
+        self.get_accelerometer(self.built_in_port(|ports| ports.accelerometer, "accelerometer")?).await
+    }
+
+    async fn get_hub_gyro(&self) -> Result<Gyro> {
+        self.get_gyro(self.built_in_port(|ports| ports.gyro, "gyro")?).await
+    }
+
+    async fn get_hub_tilt_sensor(&self) -> Result<TiltSensor> {
+        self.get_tilt_sensor(self.built_in_port(|ports| ports.tilt, "tilt sensor")?).await
+    }
+
+    async fn get_hub_voltage_sensor(&self) -> Result<VoltageSensor> {
+        self.get_voltage_sensor(self.built_in_port(|ports| ports.voltage, "voltage sensor")?).await
+    }
+
+    async fn get_hub_current_sensor(&self) -> Result<CurrentSensor> {
+        self.get_current_sensor(self.built_in_port(|ports| ports.current, "current sensor")?).await
+    }
+
+    async fn get_color_distance_sensor(&self, port_id: u8) -> Result<ColorDistanceSensor> {
+        _ = self.expect_device(port_id, &[PortType::ColorDistanceSensor], "color & distance sensor").await?;
+        Ok(ColorDistanceSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_technic_color_sensor(&self, port_id: u8) -> Result<TechnicColorSensor> {
+        _ = self.expect_device(port_id, &[PortType::TechnicColorSensor], "color sensor").await?;
+        Ok(TechnicColorSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_technic_distance_sensor(&self, port_id: u8) -> Result<TechnicDistanceSensor> {
+        _ = self.expect_device(port_id, &[PortType::TechnicDistanceSensor], "distance sensor").await?;
+        Ok(TechnicDistanceSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn get_technic_force_sensor(&self, port_id: u8) -> Result<TechnicForceSensor> {
+        _ = self.expect_device(port_id, &[PortType::TechnicForceSensor], "force sensor").await?;
+        Ok(TechnicForceSensor {
+            hub: self.clone(),
+            port_id,
+        })
+    }
+
+    async fn create_virtual_port(&self, port_a: u8, port_b: u8) -> Result<u8> {
+        let mut attachments = self.communicator.dispatcher().attachment_stream();
+        self.communicator.send_message(
+            MessageTypes::VirtualPortSetup,
+            VirtualPortSetupParams::Connect { port_a, port_b },
+        ).await?;
+        // The hub announces the new port with an attached virtual IO event
+        let wait_for_port = async {
+            while let Some(event) = attachments.next().await {
+                if let AttachmentEvent::Attached(device) = event {
+                    match device.virtual_members {
+                        Some((a, b)) if (a, b) == (port_a, port_b) || (b, a) == (port_a, port_b) => {
+                            return Some(device.port_id);
+                        },
+                        _ => (),
+                    }
+                }
+            }
+            None
+        };
+        match time::timeout(self.communicator.reply_timeout(), wait_for_port).await {
+            Ok(Some(port_id)) => Ok(port_id),
+            _ => bail!("[Error] The hub didn't create a virtual port for ports {} and {}", port_a, port_b),
+        }
+    }
+
+    async fn destroy_virtual_port(&self, port_id: u8) -> Result<()> {
+        let mut attachments = self.communicator.dispatcher().attachment_stream();
+        self.communicator.send_message(
+            MessageTypes::VirtualPortSetup,
+            VirtualPortSetupParams::Disconnect { port_id },
+        ).await?;
+        let wait_for_detach = async {
+            while let Some(event) = attachments.next().await {
+                if let AttachmentEvent::Detached { port_id: detached, .. } = event {
+                    if detached == port_id {
+                        return true;
+                    }
+                }
+            }
+            false
+        };
+        match time::timeout(self.communicator.reply_timeout(), wait_for_detach).await {
+            Ok(true) => Ok(()),
+            _ => bail!("[Error] The hub didn't remove virtual port {}", port_id),
+        }
+    }
+
+    async fn get_synced_motor_pair(&self, port_a: u8, port_b: u8) -> Result<SyncedMotorPair> {
+        // Both have to be motors
+        _ = self.get_motor(port_a).await?;
+        _ = self.get_motor(port_b).await?;
+
+        // The members may be listed either way round
+        let table = self.communicator.dispatcher().port_table();
+        let existing = table.devices().values().find_map(|device| match device.virtual_members {
+            Some(members) if members == (port_a, port_b) => Some((device.port_id, false)),
+            Some(members) if members == (port_b, port_a) => Some((device.port_id, true)),
+            _ => None,
+        });
+        let (port_id, swapped) = match existing {
+            Some(existing) => existing,
+            None => (self.create_virtual_port(port_a, port_b).await?, false),
+        };
+        Ok(SyncedMotorPair {
+            hub: self.clone(),
+            port_id,
+            port_a,
+            port_b,
+            swapped,
+        })
+    }
+
+
+
+}
+
+// Reconnects whenever the link drops. Ends along with the last clone of the hub.
+async fn supervise_connection(hub: WeakHub, mut events: NotificationStream<ConnectionEvent>) {
+    while let Some(event) = events.next().await {
+        if event != ConnectionEvent::Disconnected {
+            continue;
+        }
+        match hub.upgrade() {
+            Some(hub) => hub.reconnect().await,
+            None => return,
+        }
+    }
+}
+
+#[async_trait]
+impl HubPropertiesType for Hub {
+
+    async fn get_hub_property(&self, property: HubPropertiesProperties) -> Result<HubPropertyValue> {
+        let msg = self.communicator.request(
+            MessageTypes::HubProperties,
+            HubPropertiesParams {
+                property,
+                operation:  HubPropertiesOperations::RequestUpdate,
+                payload:    Vec::new(),
+            },
+            ExpectedReply::hub_property(property),
+        ).await?;
+        match UpstreamMessage::parse(&msg)? {
+            UpstreamMessage::HubProperties(reply) => reply.value(),
+            other => bail!("[Error] Unexpected reply to hub property request: {:?}", other.message_type()),
+        }
+    }
+
+    async fn set_advertising_name(&self, name: &str) -> Result<()> {
+        if name.is_empty() || name.len() > MAX_ADVERTISING_NAME_LENGTH || !name.is_ascii() {
+            bail!("[Error] Advertising name has to be 1 to {} ASCII characters", MAX_ADVERTISING_NAME_LENGTH)
+        }
+        self.communicator.send_message(
+            MessageTypes::HubProperties,
+            HubPropertiesParams {
+                property:   HubPropertiesProperties::AdvertisingName,
+                operation:  HubPropertiesOperations::Set,
+                payload:    name.as_bytes().to_vec(),
+            }
+        ).await
+    }
+
+    async fn reset_advertising_name(&self) -> Result<()> {
+        self.communicator.send_message(
+            MessageTypes::HubProperties,
+            HubPropertiesParams {
+                property:   HubPropertiesProperties::AdvertisingName,
+                operation:  HubPropertiesOperations::Reset,
+                payload:    Vec::new(),
+            }
+        ).await
+    }
+
+    async fn enable_hub_property_updates(
+        &self,
+        property: HubPropertiesProperties,
+    ) -> Result<NotificationStream<HubPropertyValue>> {
+        // Subscribing first - the hub sends the current value right away
+        let updates = self.communicator.dispatcher().hub_property_stream(property);
+        self.communicator.send_message(
+            MessageTypes::HubProperties,
+            HubPropertiesParams {
+                property,
+                operation:  HubPropertiesOperations::EnableUpdates,
+                payload:    Vec::new(),
+            }
+        ).await?;
+        Ok(updates)
+    }
+
+    async fn disable_hub_property_updates(&self, property: HubPropertiesProperties) -> Result<()> {
+        self.communicator.send_message(
+            MessageTypes::HubProperties,
+            HubPropertiesParams {
+                property,
+                operation:  HubPropertiesOperations::DisableUpdates,
+                payload:    Vec::new(),
+            }
+        ).await
+    }
 }
 
-// (TODO) Not fully implemented yet
+// The size and meaning of the data depend on the current mode of the port
 #[derive(Debug)]
 pub struct PortInfoValueReply {
-    pub port_type: Option<PortType>,
+    pub port_id:    u8,
+    pub data:       Vec<u8>,
 }
 
-// (TODO) Not fully implemented yet
 #[derive(Debug)]
 pub struct PortInfoCombinationsReply {
-    pub data: Vec<u8>,
+    pub port_id:        u8,
+    pub combinations:   Vec<ModeSet>,   // The index of each is the combination index used in setup
 }
 
 #[derive(Debug)]
@@ -219,10 +1018,10 @@ fn parse_capabilities(capabilities: u8) -> Vec<PortInfoModeReplyCapabilities> {
     if capabilities >> 1 & 0x1 == 0x1 {
         res.push(PortInfoModeReplyCapabilities::Input);
     }
-    if capabilities >> 1 & 0x1 == 0x1 {
+    if capabilities >> 2 & 0x1 == 0x1 {
         res.push(PortInfoModeReplyCapabilities::LogicalCombinable);
     }
-    if capabilities >> 1 & 0x1 == 0x1 {
+    if capabilities >> 3 & 0x1 == 0x1 {
         res.push(PortInfoModeReplyCapabilities::LogicalSynchronizable);
     } 
     res
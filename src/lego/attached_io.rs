@@ -0,0 +1,120 @@
+// What is plugged into each port, as reported by HubAttachedIO (0x04) messages.
+// The hub reports every attached device upon connection, and again on every plug / unplug.
+
+use std::collections::HashMap;
+
+use super::consts::PortType;
+use super::upstream_messages::{
+    AttachedIOEvent,
+    HubAttachedIOMessage,
+    VersionNumber,
+};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachedDevice {
+    pub port_id:            u8,
+    pub io_type_id:         u16,
+    pub io_type:            Option<PortType>,
+    pub hardware_revision:  Option<VersionNumber>,      // Not reported for virtual ports
+    pub software_revision:  Option<VersionNumber>,      // Not reported for virtual ports
+    pub virtual_members:    Option<(u8, u8)>,           // The two physical ports, when this is a virtual port
+}
+
+impl AttachedDevice {
+    pub fn is_virtual(&self) -> bool {
+        self.virtual_members.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachmentEvent {
+    Attached(AttachedDevice),
+    Detached {
+        port_id:    u8,
+        previous:   Option<AttachedDevice>,
+    },
+}
+
+impl AttachmentEvent {
+    pub fn port_id(&self) -> u8 {
+        match self {
+            AttachmentEvent::Attached(device) => device.port_id,
+            AttachmentEvent::Detached { port_id, .. } => *port_id,
+        }
+    }
+}
+
+
+// port id -> attached device
+#[derive(Debug, Clone, Default)]
+pub struct PortTable {
+    devices:    HashMap<u8, AttachedDevice>,
+}
+
+impl PortTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, port_id: u8) -> Option<&AttachedDevice> {
+        self.devices.get(&port_id)
+    }
+
+    pub fn devices(&self) -> &HashMap<u8, AttachedDevice> {
+        &self.devices
+    }
+
+    // The virtual port the given physical port is currently a member of
+    pub fn virtual_port_of(&self, port_id: u8) -> Option<u8> {
+        self.devices
+            .values()
+            .find(|device| match device.virtual_members {
+                Some((a, b)) => a == port_id || b == port_id,
+                None => false,
+            })
+            .map(|device| device.port_id)
+    }
+
+    pub fn remove_virtual_ports(&mut self) -> Vec<AttachedDevice> {
+        let virtual_ports: Vec<u8> = self.devices.values()
+            .filter(|device| device.is_virtual())
+            .map(|device| device.port_id)
+            .collect();
+        virtual_ports.iter().filter_map(|port_id| self.devices.remove(port_id)).collect()
+    }
+
+    pub fn apply(&mut self, msg: &HubAttachedIOMessage) -> AttachmentEvent {
+        let port_id = msg.port_id;
+        match &msg.event {
+            AttachedIOEvent::DetachedIO => AttachmentEvent::Detached {
+                port_id,
+                previous: self.devices.remove(&port_id),
+            },
+            AttachedIOEvent::AttachedIO { io_type_id, io_type, hardware_revision, software_revision } => {
+                let device = AttachedDevice {
+                    port_id,
+                    io_type_id:         *io_type_id,
+                    io_type:            *io_type,
+                    hardware_revision:  Some(*hardware_revision),
+                    software_revision:  Some(*software_revision),
+                    virtual_members:    None,
+                };
+                self.devices.insert(port_id, device.clone());
+                AttachmentEvent::Attached(device)
+            },
+            AttachedIOEvent::AttachedVirtualIO { io_type_id, io_type, port_a, port_b } => {
+                let device = AttachedDevice {
+                    port_id,
+                    io_type_id:         *io_type_id,
+                    io_type:            *io_type,
+                    hardware_revision:  None,
+                    software_revision:  None,
+                    virtual_members:    Some((*port_a, *port_b)),
+                };
+                self.devices.insert(port_id, device.clone());
+                AttachmentEvent::Attached(device)
+            },
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// Port Output Commands may ask for feedback (see StartupAndCompletionInfo).
+// The hub then reports on the command with PortOutputCommandFeedback (0x82) messages,
+// and a CommandHandle turns these into something to await on.
+
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+
+use super::MessageTypes;
+use super::NotificationStream;
+use super::UpstreamMessage;
+use super::upstream_messages::PortOutputFeedback;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome {
+    Completed,      // Buffer empty and the command completed
+    Discarded,      // The command was discarded (e.g. a newer command with ExecuteImmediately)
+    Busy,           // The port is busy and its buffer is full
+    Idle,           // The port went idle without reporting a completion
+}
+
+impl CommandOutcome {
+    // The outcome of a command already in progress. None means it is still in progress.
+    // "In progress" refers to the next command then, e.g. "in progress" along with "discarded" means
+    // this command was discarded in favor of the next one.
+    pub fn from_feedback(feedback: &PortOutputFeedback) -> Option<Self> {
+        if feedback.current_command_discarded {
+            Some(CommandOutcome::Discarded)
+        } else if feedback.buffer_empty_command_completed {
+            Some(CommandOutcome::Completed)
+        } else if feedback.busy_full {
+            Some(CommandOutcome::Busy)
+        } else if feedback.idle {
+            Some(CommandOutcome::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+
+// Returned by every output command.
+// Awaiting is optional - dropping the handle doesn't affect the command.
+pub struct CommandHandle {
+    port_id:            u8,
+    messages:           Option<NotificationStream<UpstreamMessage>>,
+    previous_pending:   bool,       // An earlier command on the port wasn't finished when this one was sent
+    accept_timeout:     Duration,   // For the hub to take the command up, unless it waits for the earlier one
+}
+
+impl CommandHandle {
+    // The stream has to be subscribed before the command is sent, so no feedback is missed.
+    // Feedback and errors come in the same stream, so their order is kept.
+    pub fn new(
+        port_id: u8,
+        messages: NotificationStream<UpstreamMessage>,
+        previous_pending: bool,
+        accept_timeout: Duration,
+    ) -> Self {
+        Self { port_id, messages: Some(messages), previous_pending, accept_timeout }
+    }
+
+    // For commands sent without asking for feedback
+    pub fn without_feedback(port_id: u8) -> Self {
+        Self { port_id, messages: None, previous_pending: false, accept_timeout: Duration::ZERO }
+    }
+
+    pub fn port_id(&self) -> u8 {
+        self.port_id
+    }
+
+    pub fn has_feedback(&self) -> bool {
+        self.messages.is_some()
+    }
+
+    // Resolves once the hub reports the command completed, was discarded, or the port went idle / busy.
+    // While an earlier command is pending on the port, feedback before this command is accepted - its first
+    // "in progress" or "discarded" report - is about the earlier one, and is ignored. Otherwise the first report
+    // is this command's, even a lone "completed" of a command finished right away.
+    // Fails if the hub doesn't take the command up within accept_timeout - running it may take any time after that.
+    pub async fn wait(self) -> Result<CommandOutcome> {
+        self.wait_until(None).await
+    }
+
+    // wait(), failing if the command hasn't finished within the timeout
+    pub async fn wait_for(self, timeout: Duration) -> Result<CommandOutcome> {
+        self.wait_until(Some(Instant::now() + timeout)).await
+    }
+
+    async fn wait_until(self, deadline: Option<Instant>) -> Result<CommandOutcome> {
+        let port_id = self.port_id;
+        let mut messages = match self.messages {
+            Some(messages) => messages,
+            None => bail!("[Error] No feedback was requested for the command on port {}", port_id),
+        };
+        // An earlier command may keep this one in the buffer for as long as it runs
+        let accept_deadline = match self.previous_pending {
+            true => None,
+            false => Some(Instant::now() + self.accept_timeout),
+        };
+        let mut accepted = false;
+        loop {
+            let next_deadline = match (accepted, accept_deadline, deadline) {
+                (false, Some(accept_deadline), Some(deadline)) => Some(accept_deadline.min(deadline)),
+                (false, Some(accept_deadline), None) => Some(accept_deadline),
+                (_, _, deadline) => deadline,
+            };
+            let next = match next_deadline {
+                Some(next_deadline) => match time::timeout_at(next_deadline, messages.next()).await {
+                    Ok(next) => next,
+                    Err(_) if accepted => bail!("[Error] The command on port {} didn't finish in time", port_id),
+                    Err(_) => bail!("[Error] The hub didn't take up the command on port {} in time", port_id),
+                },
+                None => messages.next().await,
+            };
+            let msg = match next {
+                Some(msg) => msg,
+                None => bail!("[Error] The hub stopped sending feedback for port {}", port_id),
+            };
+            match msg {
+                UpstreamMessage::PortOutputCommandFeedback(feedback) => {
+                    for port_feedback in feedback.feedbacks.iter().filter(|f| f.port_id == port_id) {
+                        if !accepted {
+                            let taken_up = port_feedback.buffer_empty_command_in_progress || port_feedback.current_command_discarded;
+                            if !taken_up && self.previous_pending {
+                                continue;
+                            }
+                            accepted = true;
+                            // Any other bits are about the earlier command
+                            if port_feedback.buffer_empty_command_in_progress {
+                                continue;
+                            }
+                        }
+                        if let Some(outcome) = CommandOutcome::from_feedback(port_feedback) {
+                            return Ok(outcome);
+                        }
+                    }
+                },
+                // Errors don't name the port. A command the hub has accepted can't be rejected anymore,
+                // so errors are only taken as ours before that.
+                UpstreamMessage::GenericErrorMessages(err) if !accepted && err.command_type == Some(MessageTypes::PortOutputCommand) => {
+                    bail!(err.to_string())
+                },
+                _ => {},
+            }
+        }
+    }
+}
@@ -1,18 +1,28 @@
-use std::pin::Pin;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use btleplug::api::{Peripheral as _, Characteristic, Service, WriteType, ValueNotification};
-use btleplug::platform::{Peripheral};
-
-use anyhow::{Result, anyhow, Ok};
+use anyhow::{Result, Ok, bail};
 use num_traits::ToPrimitive;
-use tokio_stream::Stream;
+use tokio::sync::{broadcast, Mutex};
+use tokio::time;
 
 
-use super::check_for_lego_error;
+use super::ReplyTimeoutError;
+use super::UpstreamMessage;
 use super::{MessageTypes, message_parameters::Serialized};
+use super::message_parameters::{
+    HubPropertiesOperations,
+    HubPropertiesProperties,
+};
+use super::Transport;
+use super::dispatcher::Dispatcher;
 
 pub const MAX_MESSAGE_SIZE: usize = 130;
 
+// How long to wait for the hub to answer a request
+pub const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
 
 
 pub struct CommonMessageHeader {}
@@ -24,73 +34,166 @@ impl CommonMessageHeader {
 }
 
 
-pub struct Communicator {
-    peripheral: Peripheral,
-    characteristic: Characteristic,
+// Which upstream message answers a request.
+// E.g. PortInformationRequest (0x21) -> PortInformation (0x43) of the same port.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectedReply {
+    pub message_type:   MessageTypes,
+    pub port_id:        Option<u8>,
+    pub hub_property:   Option<HubPropertiesProperties>,
 }
 
-impl Communicator {
-    pub async fn new(peripheral: Peripheral) -> Result<Self> {
-        peripheral.discover_services().await?;
-        
-        let srvc: Option<Service>;
-        let mut c: Option<Characteristic> = None;
-        for service in peripheral.services() {
-            srvc = Some(service);
-            for characteristic in srvc.unwrap().characteristics {
-                c = Some(characteristic);
-                break;
-            }
-            break;
-        }
-        println!("Characteristics: {:?}", c);
-        Ok(Self { peripheral, characteristic: c.unwrap() })
+impl ExpectedReply {
+    pub fn new(message_type: MessageTypes, port_id: Option<u8>) -> Self {
+        Self { message_type, port_id, hub_property: None }
     }
 
-    pub async fn send_message<T>(&self, mt: MessageTypes, mp: T) -> Result<()>
-    where
-        T: Serialized,
-    {
-        let write_type = WriteType::WithResponse;
+    // HubProperties (0x01) update of a specific property
+    pub fn hub_property(property: HubPropertiesProperties) -> Self {
+        Self { message_type: MessageTypes::HubProperties, port_id: None, hub_property: Some(property) }
+    }
 
-        let mut data = CommonMessageHeader::get_header(mt);
-        data.append(mp.serialize().as_mut());
-        let size = data.len();
-        data[0] = size.to_u8().unwrap();
-        if size > 127 {
-            data.insert(1, 0x01);
+    pub fn matches(&self, msg: &UpstreamMessage) -> bool {
+        if msg.message_type() != self.message_type {
+            return false;
         }
+        if let (Some(property), UpstreamMessage::HubProperties(update)) = (self.hub_property, msg) {
+            return update.property == property && update.operation == HubPropertiesOperations::Update;
+        }
+        match (self.port_id, msg) {
+            (None, _) => true,
+            (Some(port_id), UpstreamMessage::PortOutputCommandFeedback(feedback)) => {
+                feedback.feedbacks.iter().any(|f| f.port_id == port_id)
+            },
+            (Some(port_id), _) => msg.port_id() == Some(port_id),
+        }
+    }
+}
 
-        let res = self.peripheral.write(
-            &self.characteristic, 
-            &data, 
-            write_type).await;
 
-        if res.is_err() {
-            Err(anyhow!("Couldn't send the message"))
-        } else {
-            Ok(())
+// (Request message type, port) -> lock
+type InFlightLocks = HashMap<(u8, Option<u8>), Arc<Mutex<()>>>;
+
+pub struct Communicator<T: Transport> {
+    transport:      T,
+    dispatcher:     Dispatcher,
+    reply_timeout:  std::sync::Mutex<Duration>,
+    in_flight:      std::sync::Mutex<InFlightLocks>,
+    type_counts:    std::sync::Mutex<HashMap<u8, usize>>,   // Requests in flight per message type, across ports
+}
+
+impl<T: Transport> Communicator<T> {
+    pub async fn new(transport: T) -> Result<Self> {
+        let dispatcher = Dispatcher::new(&transport).await?;
+        Ok(Self {
+            transport,
+            dispatcher,
+            reply_timeout:  std::sync::Mutex::new(DEFAULT_REPLY_TIMEOUT),
+            in_flight:      std::sync::Mutex::new(HashMap::new()),
+            type_counts:    std::sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn set_reply_timeout(&self, reply_timeout: Duration) {
+        *self.reply_timeout.lock().unwrap() = reply_timeout;
+    }
+
+    pub fn reply_timeout(&self) -> Duration {
+        *self.reply_timeout.lock().unwrap()
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn dispatcher(&self) -> &Dispatcher {
+        &self.dispatcher
+    }
+
+    pub async fn send_message<P>(&self, mt: MessageTypes, mp: P) -> Result<()>
+    where
+        P: Serialized,
+    {
+        if !self.dispatcher.is_connected() {
+            bail!("[Error] The hub is disconnected");
+        }
+        let data = self.get_message_only(mt, mp).await?;
+        let res = self.transport.write(&data).await;
+        if res.is_err() && !self.transport.is_connected().await {
+            self.dispatcher.link_lost();
         }
+        res
     }
 
-    pub async fn read_message(&self) -> Result<Vec<u8>> {
-        let mut res = self.peripheral.read(&self.characteristic).await?;
-        if res.is_empty() {
-            res = self.peripheral.read(&self.characteristic).await?;
+    // Connects the transport again, and resumes the dispatcher with its new upstream
+    pub async fn reconnect(&self) -> Result<()> {
+        self.transport.reconnect().await?;
+        self.dispatcher.resume(&self.transport).await
+    }
+
+    // Sends the message and waits for the matching reply (see ExpectedReply).
+    // Unrelated upstream traffic is left for the other subscribers of the dispatcher.
+    // Returns the raw reply frame, which is known to decode as an UpstreamMessage.
+    pub async fn request<P>(&self, mt: MessageTypes, mp: P, expected: ExpectedReply) -> Result<Vec<u8>>
+    where
+        P: Serialized,
+    {
+        // Only one request per message type and port is in flight, so replies are told apart by the port.
+        let in_flight = self.in_flight_lock(mt, expected.port_id);
+        let _guard = in_flight.lock().await;
+        // GenericErrorMessages only carry the command type though. With requests of the type in flight for
+        // other ports, an error is taken as ours only if no reply comes instead.
+        let count = TypeCount::new(&self.type_counts, mt as u8);
+        let mut unclaimed_error = None;
+
+        // Subscribing before sending, so a quick reply won't be missed
+        let mut frames = self.dispatcher.subscribe_frames();
+        self.send_message(mt, mp).await?;
+
+        let wait_for_reply = async {
+            loop {
+                let frame = match frames.recv().await {
+                    std::result::Result::Ok(frame) => frame,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => bail!("The hub is no longer sending messages"),
+                };
+                match UpstreamMessage::parse(&frame) {
+                    std::result::Result::Ok(UpstreamMessage::GenericErrorMessages(err)) if err.command_type == Some(mt) => {
+                        if count.is_alone() {
+                            bail!(err.to_string())
+                        }
+                        unclaimed_error = Some(err);
+                    },
+                    std::result::Result::Ok(msg) if expected.matches(&msg) => return Ok(frame),
+                    _ => continue,
+                }
+            }
+        };
+
+        match time::timeout(self.reply_timeout(), wait_for_reply).await {
+            std::result::Result::Ok(res) => res,
+            Err(_) if unclaimed_error.is_some() => bail!(unclaimed_error.unwrap().to_string()),
+            Err(_) => Err(anyhow::Error::new(ReplyTimeoutError {
+                request:    mt,
+                reply:      expected.message_type,
+                port_id:    expected.port_id,
+            })),
         }
-        check_for_lego_error(&res)?;
-        Ok(res)
     }
 
-    pub async fn get_notification_stream(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
-        self.peripheral.subscribe(&self.characteristic).await?;
-        Ok(self.peripheral.notifications().await?)
+    fn in_flight_lock(&self, mt: MessageTypes, port_id: Option<u8>) -> Arc<Mutex<()>> {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .entry((mt as u8, port_id))
+            .or_default()
+            .clone()
     }
 
     // This function is mainly for debugging and testing
-    pub async fn get_message_only<T>(&self, mt: MessageTypes, mp: T) -> Result<Vec<u8>>
+    pub async fn get_message_only<P>(&self, mt: MessageTypes, mp: P) -> Result<Vec<u8>>
     where
-        T: Serialized,
+        P: Serialized,
     {
         let mut data = CommonMessageHeader::get_header(mt);
         data.append(mp.serialize().as_mut());
@@ -104,4 +207,30 @@ impl Communicator {
 
         Ok(data)
     }
-}
\ No newline at end of file
+}
+
+
+// Counts a request in Communicator::type_counts for as long as it lives
+struct TypeCount<'a> {
+    counts: &'a std::sync::Mutex<HashMap<u8, usize>>,
+    mt:     u8,
+}
+
+impl<'a> TypeCount<'a> {
+    fn new(counts: &'a std::sync::Mutex<HashMap<u8, usize>>, mt: u8) -> Self {
+        *counts.lock().unwrap().entry(mt).or_default() += 1;
+        Self { counts, mt }
+    }
+
+    fn is_alone(&self) -> bool {
+        self.counts.lock().unwrap().get(&self.mt) == Some(&1)
+    }
+}
+
+impl Drop for TypeCount<'_> {
+    fn drop(&mut self) {
+        if let Some(count) = self.counts.lock().unwrap().get_mut(&self.mt) {
+            *count -= 1;
+        }
+    }
+}
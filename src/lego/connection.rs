@@ -0,0 +1,44 @@
+// The state of the link to the hub.
+// The Dispatcher notices a dropped link (the upstream ends, or a write fails on a disconnected transport),
+// and the Hub reconnects according to its ReconnectPolicy, if it has one.
+
+use std::time::Duration;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected,                  // Back again, with the input formats, combined modes and virtual ports restored
+    Disconnected,
+    Reconnecting(u32),          // The attempt number, starting with 1
+    RestoreFailed(u8),          // The port whose setup couldn't be restored, sent before Connected
+    VirtualPortMoved(u8, u8),   // The old and the new id of a restored virtual port, sent before Connected.
+                                // Synced motor pairs holding the old id have to be fetched again.
+    GaveUp,                     // No more attempts - the hub is gone for good
+}
+
+
+// Exponential backoff: initial_delay before the first attempt, then twice as long each time, up to max_delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub max_attempts:   u32,
+    pub initial_delay:  Duration,
+    pub max_delay:      Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts:   5,
+            initial_delay:  Duration::from_millis(500),
+            max_delay:      Duration::from_secs(8),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    // The wait before the given attempt (1 based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
@@ -1,4 +1,5 @@
 use num_derive::FromPrimitive;
+use uuid::{uuid, Uuid};
 
 
 
@@ -6,18 +7,79 @@ use num_derive::FromPrimitive;
 /********* Hub Related Consts **********/
 /***************************************/
 
-// (TODO) Implement device checking of these values
+// Every LEGO Wireless Protocol hub advertises this service
+pub const LEGO_HUB_SERVICE_UUID: Uuid = uuid!("00001623-1212-efde-1623-785feabcd123");
+
+// The key of the LEGO manufacturer data in the advertisement (LEGO System A/S)
+pub const LEGO_MANUFACTURER_ID: u16 = 0x0397;
+
+// The "system type and device number" byte of the advertised manufacturer data:
+// 3 bits of system type (LEGO Duplo, LEGO System, LEGO Technic), then 5 bits of device number.
+// Values are from https://lego.github.io/lego-ble-wireless-protocol-docs/#system-type-and-device-number
+#[derive(Debug, FromPrimitive, PartialEq, Eq, Clone, Copy)]
+pub enum HubKind {
+    DuploTrain          = 0x20,     // # item: 10874
+    MoveHub             = 0x40,     // # item: 88006 (BOOST)
+    CityHub             = 0x41,     // # item: 88009
+    Remote              = 0x42,     // # item: 88010
+    Mario               = 0x43,
+    Luigi               = 0x44,
+    Peach               = 0x45,
+    TechnicHub          = 0x80,     // # item: 88012
+    SpikePrimeHub       = 0x81,     // Also the MINDSTORMS Robot Inventor hub
+    SpikeEssentialHub   = 0x83,
+}
+
+// The ports of a hub's built-in devices. None where the hub doesn't have the device.
+// Ports of plugged in devices are labelled on the hub, see e.g. TechnicHubPorts.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HubPorts {
+    pub led:            Option<u8>,
+    pub current:        Option<u8>,
+    pub voltage:        Option<u8>,
+    pub accelerometer:  Option<u8>,
+    pub gyro:           Option<u8>,
+    pub tilt:           Option<u8>,
+}
+
+/* Below ports are taken from https://github.com/nathankellenicki/node-poweredup/blob/master/src/consts.ts */
+impl HubKind {
+    // None for the hubs whose built-in devices aren't known to this crate
+    pub fn ports(&self) -> Option<HubPorts> {
+        let none = HubPorts { led: None, current: None, voltage: None, accelerometer: None, gyro: None, tilt: None };
+        match self {
+            HubKind::TechnicHub => Some(HubPorts {
+                led:            Some(TechnicHubPorts::LED as u8),
+                current:        Some(TechnicHubPorts::CURRENT as u8),
+                voltage:        Some(TechnicHubPorts::VOLTAGE as u8),
+                accelerometer:  Some(TechnicHubPorts::ACCELEROMETER as u8),
+                gyro:           Some(TechnicHubPorts::GYRO as u8),
+                tilt:           Some(TechnicHubPorts::TILT as u8),
+            }),
+            HubKind::SpikeEssentialHub => Some(HubPorts {
+                led:            Some(0x31),
+                current:        Some(0x3B),
+                voltage:        Some(0x3C),
+                accelerometer:  Some(0x61),
+                gyro:           Some(0x62),
+                tilt:           Some(0x63),
+            }),
+            // The Move hub's tilt sensor (0x3A) is a PortType::MoveHubTiltSensor, which ports::TiltSensor doesn't read
+            HubKind::MoveHub | HubKind::CityHub => Some(HubPorts { led: Some(0x32), current: Some(0x3B), voltage: Some(0x3C), ..none }),
+            HubKind::Remote => Some(HubPorts { led: Some(0x34), voltage: Some(0x3B), ..none }),
+            HubKind::DuploTrain => Some(HubPorts { led: Some(0x11), voltage: Some(0x14), ..none }),
+            HubKind::Mario | HubKind::Luigi | HubKind::Peach | HubKind::SpikePrimeHub => None,
+        }
+    }
+}
+
+// Kept for a release, so code naming it still builds - HubKind has these and the rest of the hubs
+#[deprecated(note = "Use HubKind - HubTypes::HubHub is HubKind::CityHub")]
 pub enum HubTypes {
     TechnicHub,         // # item: 88012
     HubHub,             // # item: 88009
 }
 
-#[allow(dead_code)]
-enum HubTypesSystemId {
-    TechnicHubSystemId  = 0b1000000,
-    HubHubSystemId      = 0b1000001,
-}
-
 /* Below consts are taken from https://github.com/corneliusmunz/legoino/blob/master/src/Lpf2HubConst.h */
 /* Same values are in https://github.com/sciguy16/lego-powered-up/blob/main/lego-powered-up/src/hubs.rs */
 #[derive(Clone, Copy, Debug)]
@@ -107,7 +169,71 @@ pub enum MotorModes {
     Calib   = 0x05,
 }
 
-#[derive(Debug, Clone, Copy)]
+// Modes of the Technic hub's built-in sensors. Only the ones in use are listed.
+pub enum AccelerometerModes {
+    Grv     = 0x00,     // Acceleration, 3 axes
+}
+
+pub enum GyroModes {
+    Rot     = 0x00,     // Angular velocity, 3 axes
+}
+
+pub enum TiltModes {
+    Pos     = 0x00,     // Orientation, 3 axes
+}
+
+pub enum VoltageSensorModes {
+    VltL    = 0x00,     // mV
+    VltS    = 0x01,
+}
+
+pub enum CurrentSensorModes {
+    CurL    = 0x00,     // mA
+    CurS    = 0x01,
+}
+
+// Color & Distance sensor (88007)
+pub enum ColorDistanceSensorModes {
+    Color   = 0x00,     // Discrete color, see Color
+    Prox    = 0x01,     // Proximity 0 (near) to 10 (far)
+    Count   = 0x02,     // Counter of detected objects
+    Reflt   = 0x03,     // Reflected light, %
+    Ambi    = 0x04,     // Ambient light, %
+    ColO    = 0x05,     // Output: LED color
+    RgbI    = 0x06,     // Raw red, green, blue
+    IrTx    = 0x07,     // Output: Power Functions IR code
+}
+
+// SPIKE Prime color sensor (PortType::TechnicColorSensor)
+pub enum TechnicColorSensorModes {
+    Color   = 0x00,     // Discrete color, see Color. -1 for none.
+    Reflt   = 0x01,     // Reflected light, %
+    Ambi    = 0x02,     // Ambient light, %
+    Light   = 0x03,     // Output: brightness of the 3 LEDs, %
+    RgbI    = 0x05,     // Raw red, green, blue, and overall intensity
+    Hsv     = 0x06,     // Hue, saturation, value
+}
+
+// SPIKE Prime distance sensor (PortType::TechnicDistanceSensor)
+pub enum TechnicDistanceSensorModes {
+    Distl   = 0x00,     // Distance, mm. -1 when nothing is in range.
+    Dists   = 0x01,     // Short range distance, mm - faster
+    Light   = 0x05,     // Output: brightness of the 4 light segments, %
+}
+
+// SPIKE Prime force sensor (PortType::TechnicForceSensor)
+pub enum TechnicForceSensorModes {
+    Force   = 0x00,     // Force, tenths of a newton
+    Touched = 0x01,     // 1 when touched
+}
+
+// Modes of the hub's status LED (PortType::HubLed)
+pub enum HubLedModes {
+    Color   = 0x00,     // Index of Color
+    Rgb     = 0x01,     // Red, green, blue
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PortInfoModeReplyCapabilities {
     Output                  = 0x0,  // Output (seen from Hub)
     Input                   = 0x1,  // Input (seen from Hub)
@@ -136,7 +262,7 @@ pub enum LegoErrorTypes {
 
 
 /* Below Color consts are taken from https://github.com/corneliusmunz/legoino/blob/master/src/Lpf2HubConst.h */
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum Color {
     Black       = 0,
     Pink        = 1,
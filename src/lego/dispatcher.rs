@@ -0,0 +1,391 @@
+// A single background task consumes everything the hub sends, decodes it, and fans it out.
+// Each kind of message gets its own broadcast channel, so a flood of port values
+// won't push attach events or errors out of a slow subscriber's buffer.
+
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, anyhow};
+use tokio::select;
+use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::task::JoinHandle;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::BroadcastStream;
+
+use super::Transport;
+use super::UpstreamMessage;
+use super::ConnectionEvent;
+use super::attached_io::{
+    AttachedDevice,
+    AttachmentEvent,
+    PortTable,
+};
+use super::message_parameters::{
+    HubPropertiesOperations,
+    HubPropertiesProperties,
+};
+use super::port_modes::CombinedModeSetup;
+use super::upstream_messages::{
+    GenericErrorMessage,
+    HubPropertiesMessage,
+    HubPropertyValue,
+    PortInputFormatSingleMessage,
+    PortOutputFeedback,
+    PortValueCombinedModeMessage,
+    PortValueSingleMessage,
+};
+
+pub type NotificationStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+
+type UpstreamStream = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+// How many messages a subscriber may fall behind before losing the oldest ones
+const CHANNEL_CAPACITY: usize = 256;
+
+
+pub struct Dispatcher {
+    frames_tx:          broadcast::Sender<Vec<u8>>,
+    messages_tx:        broadcast::Sender<UpstreamMessage>,
+    port_values_tx:     broadcast::Sender<PortValueSingleMessage>,
+    combined_values_tx: broadcast::Sender<PortValueCombinedModeMessage>,
+    attachments_tx:     broadcast::Sender<AttachmentEvent>,
+    feedback_tx:        broadcast::Sender<PortOutputFeedback>,
+    errors_tx:          broadcast::Sender<GenericErrorMessage>,
+    hub_properties_tx:  broadcast::Sender<HubPropertiesMessage>,
+    connection_tx:      broadcast::Sender<ConnectionEvent>,
+    port_table:         Arc<RwLock<PortTable>>,
+    input_formats:      Arc<RwLock<HashMap<u8, PortInputFormatSingleMessage>>>,
+    combined_modes:     Arc<RwLock<HashMap<u8, CombinedModeSetup>>>,
+    commands_pending:   Arc<RwLock<HashSet<u8>>>,   // Ports with an output command the hub hasn't finished
+    value_streams:      Arc<RwLock<HashMap<u8, usize>>>,    // Live port value and combined value streams, by port id
+    connected:          Arc<AtomicBool>,
+    link_lost:          Arc<Notify>,
+    upstreams_tx:       mpsc::UnboundedSender<UpstreamStream>,     // Fresh upstreams, after reconnecting
+    task:               JoinHandle<()>,
+}
+
+impl Dispatcher {
+    pub async fn new<T: Transport>(transport: &T) -> Result<Self> {
+        let mut upstream = transport.upstream().await?;
+
+        let (frames_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (messages_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (port_values_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (combined_values_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (attachments_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let port_table = Arc::new(RwLock::new(PortTable::new()));
+        let input_formats = Arc::new(RwLock::new(HashMap::new()));
+        let combined_modes = Arc::new(RwLock::new(HashMap::new()));
+        let commands_pending = Arc::new(RwLock::new(HashSet::new()));
+        let value_streams = Arc::new(RwLock::new(HashMap::new()));
+        let (feedback_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (errors_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (hub_properties_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (connection_tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let connected = Arc::new(AtomicBool::new(true));
+        let link_lost = Arc::new(Notify::new());
+        let (upstreams_tx, mut upstreams_rx) = mpsc::unbounded_channel();
+
+        let router = Router {
+            frames_tx:          frames_tx.clone(),
+            messages_tx:        messages_tx.clone(),
+            port_values_tx:     port_values_tx.clone(),
+            combined_values_tx: combined_values_tx.clone(),
+            attachments_tx:     attachments_tx.clone(),
+            port_table:         port_table.clone(),
+            input_formats:      input_formats.clone(),
+            combined_modes:     combined_modes.clone(),
+            commands_pending:   commands_pending.clone(),
+            feedback_tx:        feedback_tx.clone(),
+            errors_tx:          errors_tx.clone(),
+            hub_properties_tx:  hub_properties_tx.clone(),
+        };
+
+        let task_connection_tx = connection_tx.clone();
+        let task_connected = connected.clone();
+        let task_link_lost = link_lost.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                loop {
+                    select! {
+                        frame = upstream.next() => match frame {
+                            Some(frame) => router.route(frame),
+                            None => break,
+                        },
+                        _ = task_link_lost.notified() => break,
+                    }
+                }
+                task_connected.store(false, Ordering::SeqCst);
+                _ = task_connection_tx.send(ConnectionEvent::Disconnected);
+                // Waiting for the link to come back, see resume()
+                match upstreams_rx.recv().await {
+                    Some(next) => upstream = next,
+                    None => return,
+                }
+            }
+        });
+
+        Ok(Self {
+            frames_tx,
+            messages_tx,
+            port_values_tx,
+            combined_values_tx,
+            attachments_tx,
+            port_table,
+            input_formats,
+            combined_modes,
+            commands_pending,
+            value_streams,
+            feedback_tx,
+            errors_tx,
+            hub_properties_tx,
+            connection_tx,
+            connected,
+            link_lost,
+            upstreams_tx,
+            task,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    // For when the link is known to be down before the upstream ends, e.g. a write failed
+    pub fn link_lost(&self) {
+        if self.connected.swap(false, Ordering::SeqCst) {
+            self.link_lost.notify_one();
+        }
+    }
+
+    // Routes the frames of a fresh upstream, once the transport is connected again.
+    // Subscribers keep their streams throughout.
+    pub async fn resume<T: Transport>(&self, transport: &T) -> Result<()> {
+        let upstream = transport.upstream().await?;
+        self.upstreams_tx.send(upstream).map_err(|_| anyhow!("The dispatcher is gone"))?;
+        self.connected.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn connection_stream(&self) -> NotificationStream<ConnectionEvent> {
+        into_stream(self.connection_tx.subscribe())
+    }
+
+    // Events the dispatcher can't tell by itself, e.g. reconnection attempts
+    pub fn send_connection_event(&self, event: ConnectionEvent) {
+        _ = self.connection_tx.send(event);
+    }
+
+    // Raw frames, including those which couldn't be decoded
+    pub fn subscribe_frames(&self) -> broadcast::Receiver<Vec<u8>> {
+        self.frames_tx.subscribe()
+    }
+
+    pub fn subscribe_messages(&self) -> broadcast::Receiver<UpstreamMessage> {
+        self.messages_tx.subscribe()
+    }
+
+    pub fn message_stream(&self) -> NotificationStream<UpstreamMessage> {
+        into_stream(self.messages_tx.subscribe())
+    }
+
+    pub fn port_value_stream(&self, port_id: u8) -> NotificationStream<PortValueSingleMessage> {
+        // The stream owns the count, so dropping it ends the port's streaming
+        let count = StreamCount::new(self.value_streams.clone(), port_id);
+        Box::pin(
+            into_stream(self.port_values_tx.subscribe())
+                .filter(move |msg| count.is_of(msg.port_id))
+        )
+    }
+
+    // Raw, see port_modes::CombinedModeDecoder for splitting them into values
+    pub fn combined_value_stream(&self, port_id: u8) -> NotificationStream<PortValueCombinedModeMessage> {
+        let count = StreamCount::new(self.value_streams.clone(), port_id);
+        Box::pin(
+            into_stream(self.combined_values_tx.subscribe())
+                .filter(move |msg| count.is_of(msg.port_id))
+        )
+    }
+
+    // Whether someone holds a value stream of the port, so switching its mode would silence them
+    pub(crate) fn is_streaming(&self, port_id: u8) -> bool {
+        self.value_streams.read().unwrap().get(&port_id).is_some_and(|count| *count > 0)
+    }
+
+    pub fn attachment_stream(&self) -> NotificationStream<AttachmentEvent> {
+        into_stream(self.attachments_tx.subscribe())
+    }
+
+    // A copy of the port table, as it is now
+    pub fn port_table(&self) -> PortTable {
+        self.port_table.read().unwrap().clone()
+    }
+
+    // The input format last confirmed by the hub (PortInputFormatSingle), i.e. the mode of the port's values
+    pub fn input_format(&self, port_id: u8) -> Option<PortInputFormatSingleMessage> {
+        self.input_formats.read().unwrap().get(&port_id).cloned()
+    }
+
+    pub fn input_formats(&self) -> HashMap<u8, PortInputFormatSingleMessage> {
+        self.input_formats.read().unwrap().clone()
+    }
+
+    // The combined mode setups confirmed by the hub, by port id. Only the Hub knows the setups, so it records them.
+    pub fn combined_modes(&self) -> Vec<CombinedModeSetup> {
+        let mut setups: Vec<CombinedModeSetup> = self.combined_modes.read().unwrap().values().cloned().collect();
+        setups.sort_by_key(|setup| setup.port_id);
+        setups
+    }
+
+    pub(crate) fn record_combined_mode(&self, setup: CombinedModeSetup) {
+        self.combined_modes.write().unwrap().insert(setup.port_id, setup);
+    }
+
+    // For an output command with feedback, about to be sent.
+    // Returns whether an earlier one is still pending on the port, so its feedback may come first.
+    pub(crate) fn start_command(&self, port_id: u8) -> bool {
+        !self.commands_pending.write().unwrap().insert(port_id)
+    }
+
+    // A disconnected hub forgets its virtual ports. Returns them, by port id.
+    pub fn take_virtual_ports(&self) -> Vec<AttachedDevice> {
+        let mut virtual_ports = self.port_table.write().unwrap().remove_virtual_ports();
+        virtual_ports.sort_by_key(|device| device.port_id);
+        virtual_ports
+    }
+
+    pub fn feedback_stream(&self) -> NotificationStream<PortOutputFeedback> {
+        into_stream(self.feedback_tx.subscribe())
+    }
+
+    pub fn error_stream(&self) -> NotificationStream<GenericErrorMessage> {
+        into_stream(self.errors_tx.subscribe())
+    }
+
+    // Updates of a single hub property. Updates which can't be decoded are skipped.
+    pub fn hub_property_stream(&self, property: HubPropertiesProperties) -> NotificationStream<HubPropertyValue> {
+        Box::pin(
+            into_stream(self.hub_properties_tx.subscribe())
+                .filter(move |msg| msg.property == property && msg.operation == HubPropertiesOperations::Update)
+                .filter_map(|msg| msg.value().ok())
+        )
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Lagging subscribers simply skip the messages they've missed
+fn into_stream<T: Clone + Send + 'static>(rx: broadcast::Receiver<T>) -> NotificationStream<T> {
+    Box::pin(BroadcastStream::new(rx).filter_map(|msg| msg.ok()))
+}
+
+// Counts a value stream of a port for as long as it lives
+struct StreamCount {
+    counts:     Arc<RwLock<HashMap<u8, usize>>>,
+    port_id:    u8,
+}
+
+impl StreamCount {
+    fn new(counts: Arc<RwLock<HashMap<u8, usize>>>, port_id: u8) -> Self {
+        *counts.write().unwrap().entry(port_id).or_default() += 1;
+        Self { counts, port_id }
+    }
+
+    // A method, so the stream's closure captures the whole count rather than just its port id
+    fn is_of(&self, port_id: u8) -> bool {
+        self.port_id == port_id
+    }
+}
+
+impl Drop for StreamCount {
+    fn drop(&mut self) {
+        if let Some(count) = self.counts.write().unwrap().get_mut(&self.port_id) {
+            *count -= 1;
+        }
+    }
+}
+
+
+// The sending halves, owned by the background task.
+// A send fails only when nobody listens, which is fine.
+struct Router {
+    frames_tx:          broadcast::Sender<Vec<u8>>,
+    messages_tx:        broadcast::Sender<UpstreamMessage>,
+    port_values_tx:     broadcast::Sender<PortValueSingleMessage>,
+    combined_values_tx: broadcast::Sender<PortValueCombinedModeMessage>,
+    attachments_tx:     broadcast::Sender<AttachmentEvent>,
+    feedback_tx:        broadcast::Sender<PortOutputFeedback>,
+    errors_tx:          broadcast::Sender<GenericErrorMessage>,
+    hub_properties_tx:  broadcast::Sender<HubPropertiesMessage>,
+    port_table:         Arc<RwLock<PortTable>>,
+    input_formats:      Arc<RwLock<HashMap<u8, PortInputFormatSingleMessage>>>,
+    combined_modes:     Arc<RwLock<HashMap<u8, CombinedModeSetup>>>,
+    commands_pending:   Arc<RwLock<HashSet<u8>>>,   // Ports with an output command the hub hasn't finished
+}
+
+impl Router {
+    fn route(&self, frame: Vec<u8>) {
+        let msg = match UpstreamMessage::parse(&frame) {
+            Ok(msg) => msg,
+            // Undecodable frames still reach the raw frame subscribers, who can parse them for the error
+            Err(_) => {
+                _ = self.frames_tx.send(frame);
+                return;
+            },
+        };
+
+        match &msg {
+            UpstreamMessage::PortValueSingle(value) => {
+                _ = self.port_values_tx.send(value.clone());
+            },
+            UpstreamMessage::PortValueCombinedMode(value) => {
+                _ = self.combined_values_tx.send(value.clone());
+            },
+            UpstreamMessage::HubAttachedIO(io) => {
+                // The table is updated first, so it is up to date by the time subscribers hear about it
+                let event = self.port_table.write().unwrap().apply(io);
+                // A new device starts over with its default mode
+                self.input_formats.write().unwrap().remove(&io.port_id);
+                self.combined_modes.write().unwrap().remove(&io.port_id);
+                self.commands_pending.write().unwrap().remove(&io.port_id);
+                _ = self.attachments_tx.send(event);
+            },
+            UpstreamMessage::PortInputFormatSingle(format) => {
+                self.input_formats.write().unwrap().insert(format.port_id, format.clone());
+                // Out of combined mode - unless it is being set up again, and recorded once confirmed
+                self.combined_modes.write().unwrap().remove(&format.port_id);
+            },
+            UpstreamMessage::PortInputFormatCombinedMode(format) => {
+                // A port in combined mode has no single mode to report its values in
+                self.input_formats.write().unwrap().remove(&format.port_id);
+            },
+            UpstreamMessage::PortOutputCommandFeedback(feedback) => {
+                let mut commands_pending = self.commands_pending.write().unwrap();
+                for port_feedback in feedback.feedbacks.iter() {
+                    if port_feedback.buffer_empty_command_in_progress {
+                        commands_pending.insert(port_feedback.port_id);
+                    } else {
+                        commands_pending.remove(&port_feedback.port_id);
+                    }
+                    _ = self.feedback_tx.send(*port_feedback);
+                }
+            },
+            UpstreamMessage::GenericErrorMessages(err) => {
+                _ = self.errors_tx.send(err.clone());
+            },
+            UpstreamMessage::HubProperties(property) => {
+                _ = self.hub_properties_tx.send(property.clone());
+            },
+            _ => (),
+        }
+        // Requests wait on the raw frames, so these go out after the state above is updated
+        _ = self.frames_tx.send(frame);
+        _ = self.messages_tx.send(msg);
+    }
+}
@@ -1,34 +1,42 @@
-use num_traits::FromPrimitive;
 use anyhow::{Result, Ok, bail};
 
 use super::MessageTypes;
 
-use crate::lego::consts::LegoErrorTypes;
+use crate::lego::upstream_messages::UpstreamMessage;
 
-fn parse_lego_error(msg: &Vec<u8>) -> Result<String> {
-    let slc = msg;
-    let err_cmd = slc[3];
-    let err_code = slc[4];
-
-    let err: Option<LegoErrorTypes> = FromPrimitive::from_u8(err_code);
-    let cmd: Option<MessageTypes> = FromPrimitive::from_u8(err_cmd);
-
-    let mut cmd_str: String = "UnknownCommand".to_string();
-    cmd.map(|x| { cmd_str = format!("{:?}", x)});
-
-    let mut err_str: String = "UnknownError".to_string();
-    err.map(|x| { err_str = format!("{:?}", x)});
-
-    Ok(format!("[Error] On command {}: {}", cmd_str, err_str))
+fn parse_lego_error(msg: &[u8]) -> Result<String> {
+    match UpstreamMessage::parse(msg)? {
+        UpstreamMessage::GenericErrorMessages(err) => Ok(err.to_string()),
+        other => bail!("[Error] Expected an error message, got {:?}", other.message_type()),
+    }
 }
 
 pub fn check_for_lego_error(msg: &Vec<u8>) -> Result<()> {
     if msg.len() < 3 {
         bail!("[Error] Not a valid message")
     }
-    if msg[2] == 0x05 {
-        bail!(parse_lego_error(msg).unwrap())
+    if msg[2] == MessageTypes::GenericErrorMessages as u8 {
+        bail!(parse_lego_error(msg)?)
     }
     Ok(())
 }
 
+// Returned (through anyhow) when the hub didn't answer a request in time.
+// Callers may tell it apart with err.downcast_ref::<ReplyTimeoutError>()
+#[derive(Debug, Clone)]
+pub struct ReplyTimeoutError {
+    pub request:    MessageTypes,
+    pub reply:      MessageTypes,
+    pub port_id:    Option<u8>,
+}
+
+impl std::fmt::Display for ReplyTimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.port_id {
+            Some(port_id) => write!(f, "[Error] Timeout waiting for {:?} of port {} (request: {:?})", self.reply, port_id, self.request),
+            None => write!(f, "[Error] Timeout waiting for {:?} (request: {:?})", self.reply, self.request),
+        }
+    }
+}
+
+impl std::error::Error for ReplyTimeoutError {}
@@ -2,7 +2,10 @@
 // Simple command is transfered as is. Complicated command needs encoding.
 // See message_types for list of these commands / messages.
 
+use num_derive::FromPrimitive;
+
 use crate::lego::consts::{
+    Color,
     EndState, 
     Profile
 };
@@ -24,9 +27,10 @@ pub trait Serialized {
 pub struct HubPropertiesParams { 
     pub property:           HubPropertiesProperties,
     pub operation:          HubPropertiesOperations,
+    pub payload:            Vec<u8>,    // Only for Set. Empty otherwise.
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum HubPropertiesProperties {
     AdvertisingName                 = 0x01, // Advertising Name
@@ -46,7 +50,7 @@ pub enum HubPropertiesProperties {
     HardwareNetworkFamily           = 0x0F,	// Hardware Network Family
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 #[repr(u8)]
 pub enum HubPropertiesOperations {
     Set             = 0x01, // Set              (Downstream)
@@ -59,7 +63,9 @@ pub enum HubPropertiesOperations {
 
 impl Serialized for HubPropertiesParams {
     fn serialize(&self) -> Vec<u8> {
-        vec![self.property as u8, self.operation as u8]
+        let mut data = vec![self.property as u8, self.operation as u8];
+        data.extend_from_slice(&self.payload);
+        data
     }
 }
 
@@ -72,7 +78,7 @@ pub struct HubActionsParams {
     pub action_type:        HubActionsTypes,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum HubActionsTypes {
     SwitchOffHub            = 0x01, // Switch Off Hub
     Disconnect              = 0x02,	// Disconnect
@@ -95,6 +101,38 @@ impl Serialized for HubActionsParams {
 }
 
 
+/***************************************/
+/************** HubAlerts **************/
+/***************************************/
+
+pub struct HubAlertsParams {
+    pub alert_type:         HubAlertsTypes,
+    pub operation:          HubAlertsOperations,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum HubAlertsTypes {
+    LowVoltage              = 0x01, // Low Voltage
+    HighCurrent             = 0x02, // High Current
+    LowSignalStrength       = 0x03, // Low Signal Strength
+    OverPowerCondition      = 0x04, // Over Power Condition
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum HubAlertsOperations {
+    EnableUpdates           = 0x01, // Enable Updates   (Downstream)
+    DisableUpdates          = 0x02, // Disable Updates  (Downstream)
+    RequestUpdates          = 0x03, // Request Updates  (Downstream)
+    Update                  = 0x04, // Update           (Upstream)
+}
+
+impl Serialized for HubAlertsParams {
+    fn serialize(&self) -> Vec<u8> {
+        vec![self.alert_type as u8, self.operation as u8]
+    }
+}
+
+
 
 /***************************************/
 /******* PortInformationRequest ********/
@@ -105,7 +143,7 @@ pub struct PortInformationRequestParams {
     pub information_type:   PortInformationType,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum PortInformationType {
     PortValue                   = 0x00, // Port Value
     ModeInfo                    = 0x01, // Mode Info
@@ -129,7 +167,7 @@ pub struct PortModeInformationRequestParams {
     pub information_type:   PortModeInformationType,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum PortModeInformationType {
     Name            = 0x00,    // NAME	                                Name of the mode
     Raw             = 0x01,    // RAW	                                The raw range
@@ -171,6 +209,71 @@ impl Serialized for PortInputFormatSetupSingleParams {
 }
 
 
+/***************************************/
+/*** PortInputFormatSetupCombinedMode **/
+/***************************************/
+
+pub struct PortInputFormatSetupCombinedModeParams {
+    pub port_id:        u8,
+    pub subcommand:     CombinedModeSubcommand,
+}
+
+pub enum CombinedModeSubcommand {
+    SetModeAndDataSetCombination {
+        combination_index:  u8,
+        mode_datasets:      Vec<(u8, u8)>,  // (mode, dataset). Bit N of the value pointers refers to the Nth one.
+    },
+    LockForSetup,
+    UnlockAndStartMultiUpdateEnabled,
+    UnlockAndStartMultiUpdateDisabled,
+    ResetSensor,
+}
+
+impl Serialized for PortInputFormatSetupCombinedModeParams {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = vec![self.port_id];
+        match &self.subcommand {
+            CombinedModeSubcommand::SetModeAndDataSetCombination { combination_index, mode_datasets } => {
+                data.push(0x01);
+                data.push(*combination_index);
+                for (mode, dataset) in mode_datasets.iter() {
+                    data.push((mode & 0x0f) << 4 | (dataset & 0x0f));
+                }
+            },
+            CombinedModeSubcommand::LockForSetup => data.push(0x02),
+            CombinedModeSubcommand::UnlockAndStartMultiUpdateEnabled => data.push(0x03),
+            CombinedModeSubcommand::UnlockAndStartMultiUpdateDisabled => data.push(0x04),
+            CombinedModeSubcommand::ResetSensor => data.push(0x06),
+        }
+        data
+    }
+}
+
+
+/***************************************/
+/********** VirtualPortSetup ***********/
+/***************************************/
+
+pub enum VirtualPortSetupParams {
+    Disconnect {
+        port_id:    u8,     // The virtual port
+    },
+    Connect {
+        port_a:     u8,
+        port_b:     u8,
+    },
+}
+
+impl Serialized for VirtualPortSetupParams {
+    fn serialize(&self) -> Vec<u8> {
+        match self {
+            VirtualPortSetupParams::Disconnect { port_id } => vec![0x00, *port_id],
+            VirtualPortSetupParams::Connect { port_a, port_b } => vec![0x01, *port_a, *port_b],
+        }
+    }
+}
+
+
 /***************************************/
 /********** PortOutputCommand **********/
 /***************************************/
@@ -198,6 +301,13 @@ pub enum StartupAndCompletionInfo {
     ExecuteImmediatelyAndFeedback   = 0b00010001,
 }
 
+impl StartupAndCompletionInfo {
+    // Will the hub send PortOutputCommandFeedback for this command?
+    pub fn feedback_requested(&self) -> bool {
+        (*self as u8) & 0x01 == 0x01
+    }
+}
+
 
 
 
@@ -213,17 +323,26 @@ pub enum StartupAndCompletionInfo {
 
 
 pub enum SubcommandPayload {
+    StartPowerSync(StartPowerSyncPayload),
     SetAccTime(SetAccTimePayload),
     SetDecTime(SetDecTimePayload),
     StartSpeed(StartSpeedPayload),
+    StartSpeedSync(StartSpeedSyncPayload),
+    StartSpeedForTime(StartSpeedForTimePayload),
+    StartSpeedForTimeSync(StartSpeedForTimeSyncPayload),
     StartSpeedForDegrees(StartSpeedForDegreesPayload),
+    StartSpeedForDegreesSync(StartSpeedForDegreesSyncPayload),
     GotoAbsolutePosition(GotoAbsolutePositionPayload),
+    GotoAbsolutePositionSync(GotoAbsolutePositionSyncPayload),
     WriteDirectModeData(WriteDirectModeDataPayload),
 }
 
 impl Serialized for SubcommandPayload {
     fn serialize(&self) -> Vec<u8> {
         match self {
+            SubcommandPayload::StartPowerSync(payload) => {
+                payload.serialize()
+            },
             SubcommandPayload::SetAccTime(payload) => {
                 payload.serialize()
             },
@@ -233,12 +352,27 @@ impl Serialized for SubcommandPayload {
             SubcommandPayload::StartSpeed(payload) => {
                 payload.serialize()
             },
+            SubcommandPayload::StartSpeedSync(payload) => {
+                payload.serialize()
+            },
+            SubcommandPayload::StartSpeedForTime(payload) => {
+                payload.serialize()
+            },
+            SubcommandPayload::StartSpeedForTimeSync(payload) => {
+                payload.serialize()
+            },
             SubcommandPayload::StartSpeedForDegrees(payload) => {
                 payload.serialize()
             },
+            SubcommandPayload::StartSpeedForDegreesSync(payload) => {
+                payload.serialize()
+            },
             SubcommandPayload::GotoAbsolutePosition(payload) => {
                 payload.serialize()
             },
+            SubcommandPayload::GotoAbsolutePositionSync(payload) => {
+                payload.serialize()
+            },
             SubcommandPayload::WriteDirectModeData(payload) => {
                 payload.serialize()
             },
@@ -247,6 +381,25 @@ impl Serialized for SubcommandPayload {
 }
 
 
+/***************************************/
+/*********** StartPowerSync ************/
+/***************************************/
+
+pub struct StartPowerSyncPayload {
+    pub power_a:    i8,
+    pub power_b:    i8,
+}
+
+impl Serialized for StartPowerSyncPayload {
+    fn serialize(&self) -> Vec<u8> {
+        vec![
+            self.power_a.to_le_bytes()[0],
+            self.power_b.to_le_bytes()[0],
+        ]
+    }
+}
+
+
 /***************************************/
 /************* SetAccTime **************/
 /***************************************/
@@ -306,6 +459,83 @@ impl Serialized for StartSpeedPayload {
 }
 
 
+/***************************************/
+/*********** StartSpeedSync ************/
+/***************************************/
+
+pub struct StartSpeedSyncPayload {
+    pub speed_a:        i8,
+    pub speed_b:        i8,
+    pub max_power:      i8,
+    pub use_profile:    Profile,
+}
+
+impl Serialized for StartSpeedSyncPayload {
+    fn serialize(&self) -> Vec<u8> {
+        vec![
+            self.speed_a.to_le_bytes()[0],
+            self.speed_b.to_le_bytes()[0],
+            self.max_power.to_le_bytes()[0],
+            self.use_profile as u8,
+        ]
+    }
+}
+
+
+/***************************************/
+/********** StartSpeedForTime **********/
+/***************************************/
+
+pub struct StartSpeedForTimePayload {
+    pub time:           u16,        // ms
+    pub speed:          i8,
+    pub max_power:      i8,
+    pub end_state:      EndState,
+    pub use_profile:    Profile,
+}
+
+impl Serialized for StartSpeedForTimePayload {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::from(self.time.to_le_bytes());
+        data.append(vec![
+            self.speed.to_le_bytes()[0],
+            self.max_power.to_le_bytes()[0],
+            (self.end_state as u8).to_le_bytes()[0],
+            self.use_profile as u8,
+        ].as_mut());
+        data
+    }
+}
+
+
+/***************************************/
+/******** StartSpeedForTimeSync ********/
+/***************************************/
+
+pub struct StartSpeedForTimeSyncPayload {
+    pub time:           u16,        // ms
+    pub speed_a:        i8,
+    pub speed_b:        i8,
+    pub max_power:      i8,
+    pub end_state:      EndState,
+    pub use_profile:    Profile,
+}
+
+impl Serialized for StartSpeedForTimeSyncPayload {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::from(self.time.to_le_bytes());
+        data.append(vec![
+            self.speed_a.to_le_bytes()[0],
+            self.speed_b.to_le_bytes()[0],
+            self.max_power.to_le_bytes()[0],
+            (self.end_state as u8).to_le_bytes()[0],
+            self.use_profile as u8,
+        ].as_mut());
+        data
+    }
+}
+
+
 /***************************************/
 /******** StartSpeedForDegrees *********/
 /***************************************/
@@ -332,6 +562,34 @@ impl Serialized for StartSpeedForDegreesPayload {
 }
 
 
+/***************************************/
+/****** StartSpeedForDegreesSync *******/
+/***************************************/
+
+pub struct StartSpeedForDegreesSyncPayload {
+    pub degrees:        i32,
+    pub speed_a:        i8,
+    pub speed_b:        i8,
+    pub max_power:      i8,
+    pub end_state:      EndState,
+    pub use_profile:    Profile,
+}
+
+impl Serialized for StartSpeedForDegreesSyncPayload {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::from(self.degrees.to_le_bytes());
+        data.append(vec![
+            self.speed_a.to_le_bytes()[0],
+            self.speed_b.to_le_bytes()[0],
+            self.max_power.to_le_bytes()[0],
+            (self.end_state as u8).to_le_bytes()[0],
+            self.use_profile as u8,
+        ].as_mut());
+        data
+    }
+}
+
+
 /***************************************/
 /******** GotoAbsolutePosition *********/
 /***************************************/
@@ -358,6 +616,34 @@ impl Serialized for GotoAbsolutePositionPayload {
 }
 
 
+/***************************************/
+/****** GotoAbsolutePositionSync *******/
+/***************************************/
+
+pub struct GotoAbsolutePositionSyncPayload {
+    pub abs_pos_a:      i32,        // Degrees
+    pub abs_pos_b:      i32,        // Degrees
+    pub speed:          i8,
+    pub max_power:      i8,
+    pub end_state:      EndState,
+    pub use_profile:    Profile,
+}
+
+impl Serialized for GotoAbsolutePositionSyncPayload {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::from(self.abs_pos_a.to_le_bytes());
+        data.append(Vec::from(self.abs_pos_b.to_le_bytes()).as_mut());
+        data.append(vec![
+            self.speed.to_le_bytes()[0],
+            self.max_power.to_le_bytes()[0],
+            (self.end_state as u8).to_le_bytes()[0],
+            self.use_profile as u8,
+        ].as_mut());
+        data
+    }
+}
+
+
 /***************************************/
 /********* WriteDirectModeData *********/
 /***************************************/
@@ -378,6 +664,10 @@ impl Serialized for WriteDirectModeDataPayload {
 pub enum WriteDirectModeDataCommands {
     StartPower(StartPowerPayload),
     SetAbsolutePosition(SetAbsolutePositionPayload),
+    SetRgbColorNo(SetRgbColorNoPayload),
+    SetRgbColor(SetRgbColorPayload),
+    SendIrCode(SendIrCodePayload),
+    SetBrightness(SetBrightnessPayload),
 }
 
 impl Serialized for WriteDirectModeDataCommands {
@@ -389,6 +679,18 @@ impl Serialized for WriteDirectModeDataCommands {
             WriteDirectModeDataCommands::SetAbsolutePosition(payload) => {
                 payload.serialize()
             },
+            WriteDirectModeDataCommands::SetRgbColorNo(payload) => {
+                payload.serialize()
+            },
+            WriteDirectModeDataCommands::SetRgbColor(payload) => {
+                payload.serialize()
+            },
+            WriteDirectModeDataCommands::SendIrCode(payload) => {
+                payload.serialize()
+            },
+            WriteDirectModeDataCommands::SetBrightness(payload) => {
+                payload.serialize()
+            },
         }
     }
 }
@@ -424,4 +726,68 @@ impl Serialized for SetAbsolutePositionPayload {
     fn serialize(&self) -> Vec<u8> {
         Vec::from(self.position.to_le_bytes())
     }
-}
\ No newline at end of file
+}
+
+
+/***************************************/
+/************ SetRgbColorNo ************/
+/***************************************/
+
+pub struct SetRgbColorNoPayload {
+    pub color: Color,
+}
+
+impl Serialized for SetRgbColorNoPayload {
+    fn serialize(&self) -> Vec<u8> {
+        vec![self.color as u8]
+    }
+}
+
+
+/***************************************/
+/************* SetRgbColor *************/
+/***************************************/
+
+pub struct SetRgbColorPayload {
+    pub red:    u8,
+    pub green:  u8,
+    pub blue:   u8,
+}
+
+impl Serialized for SetRgbColorPayload {
+    fn serialize(&self) -> Vec<u8> {
+        vec![self.red, self.green, self.blue]
+    }
+}
+
+
+/***************************************/
+/************* SendIrCode **************/
+/***************************************/
+
+// A Power Functions IR message, as sent by the Color & Distance sensor
+pub struct SendIrCodePayload {
+    pub code: u16,
+}
+
+impl Serialized for SendIrCodePayload {
+    fn serialize(&self) -> Vec<u8> {
+        Vec::from(self.code.to_le_bytes())
+    }
+}
+
+
+/***************************************/
+/************ SetBrightness ************/
+/***************************************/
+
+// Brightness (0-100%) of each of the lights of a sensor
+pub struct SetBrightnessPayload {
+    pub brightness: Vec<u8>,
+}
+
+impl Serialized for SetBrightnessPayload {
+    fn serialize(&self) -> Vec<u8> {
+        self.brightness.clone()
+    }
+}
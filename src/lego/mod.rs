@@ -1,7 +1,14 @@
 mod message_types;
 mod communicator;
 mod errors_handler;
+mod transport;
+mod dispatcher;
+mod connection;
+mod command_feedback;
 pub mod message_parameters;
+pub mod upstream_messages;
+pub mod attached_io;
+pub mod port_modes;
 pub mod consts;
 
 pub use self::message_types::MessageTypes;
@@ -9,6 +16,30 @@ pub use self::message_types::SubcommandType;
 pub use self::communicator::CommonMessageHeader;
 pub use self::communicator::MAX_MESSAGE_SIZE;
 pub use self::communicator::Communicator;
+pub use self::communicator::ExpectedReply;
+pub use self::communicator::DEFAULT_REPLY_TIMEOUT;
+pub use self::upstream_messages::UpstreamMessage;
+pub use self::dispatcher::{
+    Dispatcher,
+    NotificationStream,
+};
+pub use self::connection::{
+    ConnectionEvent,
+    ReconnectPolicy,
+};
+pub use self::command_feedback::{
+    CommandHandle,
+    CommandOutcome,
+};
+pub use self::transport::{
+    Transport,
+    BtleTransport,
+    InMemoryTransport,
+    InMemoryPeer,
+};
 
-pub use crate::lego::errors_handler::check_for_lego_error;
+pub use crate::lego::errors_handler::{
+    check_for_lego_error,
+    ReplyTimeoutError,
+};
 
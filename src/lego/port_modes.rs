@@ -0,0 +1,429 @@
+// Typed information about the modes of a port: how their values are encoded,
+// which of them may be combined, and how a combined value update splits back into its parts.
+
+use std::fmt;
+
+use anyhow::{Result, bail};
+use num_derive::FromPrimitive;
+
+use super::attached_io::AttachedDevice;
+use super::consts::PortInfoModeReplyCapabilities;
+use super::upstream_messages::{
+    FrameReader,
+    PortValueCombinedModeMessage,
+};
+
+
+/***************************************/
+/************* ValueFormat *************/
+/***************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum DatasetType {
+    Bits8   = 0x00,
+    Bits16  = 0x01,
+    Bits32  = 0x02,
+    Float   = 0x03,
+}
+
+impl DatasetType {
+    pub fn size(&self) -> usize {
+        match self {
+            DatasetType::Bits8 => 1,
+            DatasetType::Bits16 => 2,
+            DatasetType::Bits32 | DatasetType::Float => 4,
+        }
+    }
+
+    // Integers are signed, little endian
+    pub fn read(&self, reader: &mut FrameReader) -> Result<DatasetValue> {
+        let value = match self {
+            DatasetType::Bits8 => DatasetValue::Int(reader.i8("8 bit dataset")? as i32),
+            DatasetType::Bits16 => DatasetValue::Int(reader.i16("16 bit dataset")? as i32),
+            DatasetType::Bits32 => DatasetValue::Int(reader.i32("32 bit dataset")?),
+            DatasetType::Float => DatasetValue::Float(reader.f32("float dataset")?),
+        };
+        Ok(value)
+    }
+}
+
+// VALUE FORMAT of a mode (PortModeInformation 0x80)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueFormat {
+    pub datasets:       u8,
+    pub dataset_type:   DatasetType,
+    pub figures:        u8,     // Total figures
+    pub decimals:       u8,
+}
+
+impl ValueFormat {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        let mut reader = FrameReader::new(payload);
+        Ok(Self {
+            datasets:       reader.u8("number of datasets")?,
+            dataset_type:   reader.enum_u8("dataset type")?,
+            figures:        reader.u8("total figures")?,
+            decimals:       reader.u8("decimals")?,
+        })
+    }
+
+    // Size of a whole value (all datasets), in bytes
+    pub fn size(&self) -> usize {
+        self.datasets as usize * self.dataset_type.size()
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<DatasetValue>> {
+        let mut reader = FrameReader::new(data);
+        let mut values = Vec::with_capacity(self.datasets as usize);
+        for _ in 0..self.datasets {
+            values.push(self.dataset_type.read(&mut reader)?);
+        }
+        Ok(values)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DatasetValue {
+    Int(i32),
+    Float(f32),
+}
+
+impl DatasetValue {
+    pub fn as_i32(&self) -> i32 {
+        match self {
+            DatasetValue::Int(x) => *x,
+            DatasetValue::Float(x) => *x as i32,
+        }
+    }
+
+    pub fn as_f32(&self) -> f32 {
+        match self {
+            DatasetValue::Int(x) => *x as f32,
+            DatasetValue::Float(x) => *x,
+        }
+    }
+}
+
+
+
+/***************************************/
+/*************** ModeSet ***************/
+/***************************************/
+
+// Bit per mode: bit 0 -> mode 0, etc.
+// Used for the input/output modes of a port and for its possible mode combinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModeSet(pub u16);
+
+impl ModeSet {
+    // Fails for modes above 15, which don't fit
+    pub fn from_modes(modes: &[u8]) -> Result<Self> {
+        let mut bits = 0;
+        for mode in modes.iter() {
+            if *mode > 15 {
+                bail!("[Error] Mode {} is out of range - the modes are 0 to 15", mode);
+            }
+            bits |= 1 << mode;
+        }
+        Ok(Self(bits))
+    }
+
+    pub fn contains(&self, mode: u8) -> bool {
+        mode < 16 && self.0 >> mode & 0x1 == 0x1
+    }
+
+    pub fn modes(&self) -> Vec<u8> {
+        (0..16).filter(|mode| self.contains(*mode)).collect()
+    }
+
+    // Is every mode of the other set in this one?
+    pub fn includes(&self, other: ModeSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+
+
+/***************************************/
+/********** Combined Mode Setup ********/
+/***************************************/
+
+// One dataset of one mode, as referred to by the combined mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeDataset {
+    pub mode:       u8,
+    pub dataset:    u8,
+}
+
+// Builder of a combined mode - e.g. speed + position + absolute position of a motor.
+// Hand it to HubType::setup_port_combined_mode(), which locks the port, sets up each mode, and unlocks it.
+#[derive(Debug, Clone)]
+pub struct CombinedModeSetup {
+    pub port_id:            u8,
+    pub combination_index:  u8,         // Of the possible mode combinations (see get_port_info_combinations)
+    pub modes:              Vec<(u8, u32)>,     // (mode, delta)
+    pub mode_datasets:      Vec<ModeDataset>,
+}
+
+impl CombinedModeSetup {
+    pub fn new(port_id: u8) -> Self {
+        Self {
+            port_id,
+            combination_index:  0,
+            modes:              Vec::new(),
+            mode_datasets:      Vec::new(),
+        }
+    }
+
+    pub fn combination_index(mut self, combination_index: u8) -> Self {
+        self.combination_index = combination_index;
+        self
+    }
+
+    // Adds the first dataset of a mode. delta is the change which triggers an update.
+    pub fn mode(self, mode: u8, delta: u32) -> Self {
+        self.mode_dataset(mode, 0, delta)
+    }
+
+    pub fn mode_dataset(mut self, mode: u8, dataset: u8, delta: u32) -> Self {
+        if !self.modes.iter().any(|(m, _)| *m == mode) {
+            self.modes.push((mode, delta));
+        }
+        self.mode_datasets.push(ModeDataset { mode, dataset });
+        self
+    }
+
+    pub fn mode_set(&self) -> Result<ModeSet> {
+        ModeSet::from_modes(&self.modes.iter().map(|(mode, _)| *mode).collect::<Vec<u8>>())
+    }
+}
+
+
+// Splits PortValueCombinedMode (0x46) messages back into the values of each mode/dataset.
+// Bit N of the message's pointer refers to the Nth mode/dataset of the setup.
+#[derive(Debug, Clone)]
+pub struct CombinedModeDecoder {
+    pub port_id:    u8,
+    pub entries:    Vec<(ModeDataset, DatasetType)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CombinedValue {
+    pub mode:       u8,
+    pub dataset:    u8,
+    pub value:      DatasetValue,
+}
+
+impl CombinedModeDecoder {
+    pub fn decode(&self, msg: &PortValueCombinedModeMessage) -> Result<Vec<CombinedValue>> {
+        if msg.port_id != self.port_id {
+            bail!("[Error] Combined value of port {} can't be decoded as port {}", msg.port_id, self.port_id)
+        }
+        let mut reader = FrameReader::new(&msg.data);
+        let mut values = Vec::new();
+        for (i, (mode_dataset, dataset_type)) in self.entries.iter().enumerate() {
+            if msg.mode_pointers >> i & 0x1 == 0x0 {
+                continue;
+            }
+            values.push(CombinedValue {
+                mode:       mode_dataset.mode,
+                dataset:    mode_dataset.dataset,
+                value:      dataset_type.read(&mut reader)?,
+            });
+        }
+        if msg.mode_pointers >> self.entries.len() != 0 {
+            bail!("[Error] Combined value of port {} refers to an unknown mode/dataset", msg.port_id)
+        }
+        Ok(values)
+    }
+}
+
+
+
+/***************************************/
+/********** Mode Information ***********/
+/***************************************/
+
+// RAW, PCT and SI ranges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueRange {
+    pub min:    f32,
+    pub max:    f32,
+}
+
+impl ValueRange {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        let mut reader = FrameReader::new(payload);
+        Ok(Self {
+            min:    reader.f32("range min")?,
+            max:    reader.f32("range max")?,
+        })
+    }
+
+    // Maps a value of this range onto the other one, linearly.
+    // Values outside the range are extrapolated, not clamped.
+    pub fn scale(&self, value: f32, to: &ValueRange) -> f32 {
+        let span = self.max - self.min;
+        if span == 0.0 {
+            return value;
+        }
+        to.min + (value - self.min) * (to.max - to.min) / span
+    }
+}
+
+// MAPPING - how a value may be interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MappingFlags {
+    pub supports_null:          bool,   // Bit 7
+    pub functional_mapping:     bool,   // Bit 6 - Functional Mapping 2.0+
+    pub absolute:               bool,   // Bit 4 - ABS, absolute [min..max]
+    pub relative:               bool,   // Bit 3 - REL, relative [-1..1]
+    pub discrete:               bool,   // Bit 2 - DIS, discrete [0, 1, 2, 3]
+}
+
+impl MappingFlags {
+    pub fn from_raw(flags: u8) -> Self {
+        Self {
+            supports_null:      flags >> 7 & 0x1 == 0x1,
+            functional_mapping: flags >> 6 & 0x1 == 0x1,
+            absolute:           flags >> 4 & 0x1 == 0x1,
+            relative:           flags >> 3 & 0x1 == 0x1,
+            discrete:           flags >> 2 & 0x1 == 0x1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Mapping {
+    pub input:  MappingFlags,
+    pub output: MappingFlags,
+}
+
+impl Mapping {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        let mut reader = FrameReader::new(payload);
+        Ok(Self {
+            input:  MappingFlags::from_raw(reader.u8("input mapping")?),
+            output: MappingFlags::from_raw(reader.u8("output mapping")?),
+        })
+    }
+}
+
+// NAME and SYMBOL are zero padded
+pub fn parse_mode_string(payload: &[u8]) -> String {
+    let end = payload.iter().position(|c| *c == 0).unwrap_or(payload.len());
+    String::from_utf8_lossy(&payload[..end]).to_string()
+}
+
+// Everything a mode tells about itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeDescription {
+    pub mode_id:            u8,
+    pub is_input:           bool,
+    pub is_output:          bool,
+    pub name:               String,
+    pub raw:                ValueRange,
+    pub pct:                ValueRange,
+    pub si:                 ValueRange,
+    pub symbol:             String,
+    pub mapping:            Mapping,
+    pub motor_bias:         Option<u8>,         // Not reported by every device
+    pub capability_bits:    Option<[u8; 6]>,    // Not reported by every device
+    pub value_format:       ValueFormat,
+}
+
+impl ModeDescription {
+    // Decodes a PortValueSingle (0x45) of this mode by its VALUE FORMAT, and scales it to PCT and SI
+    pub fn decode(&self, port_id: u8, data: &[u8]) -> Result<PortValue> {
+        let values = self.value_format.decode(data)?;
+        Ok(PortValue {
+            port_id,
+            mode_id:    self.mode_id,
+            raw:        values.iter().map(|x| x.as_i32()).collect(),
+            pct:        values.iter().map(|x| self.raw.scale(x.as_f32(), &self.pct)).collect(),
+            si:         values.iter().map(|x| self.raw.scale(x.as_f32(), &self.si)).collect(),
+            symbol:     self.symbol.clone(),
+        })
+    }
+}
+
+impl fmt::Display for ModeDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match (self.is_input, self.is_output) {
+            (true, true) => "in/out",
+            (true, false) => "in",
+            (false, true) => "out",
+            (false, false) => "-",
+        };
+        write!(
+            f,
+            "mode {:>2} {:<12} {:<6} {} x {:?}, raw [{}, {}], pct [{}, {}], si [{}, {}] {}",
+            self.mode_id,
+            self.name,
+            direction,
+            self.value_format.datasets,
+            self.value_format.dataset_type,
+            self.raw.min, self.raw.max,
+            self.pct.min, self.pct.max,
+            self.si.min, self.si.max,
+            self.symbol,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortDescription {
+    pub port_id:        u8,
+    pub device:         Option<AttachedDevice>,
+    pub capabilities:   Vec<PortInfoModeReplyCapabilities>,
+    pub modes:          Vec<ModeDescription>,
+    pub combinations:   Vec<ModeSet>,   // Empty unless the port is logically combinable
+}
+
+impl PortDescription {
+    pub fn mode(&self, mode_id: u8) -> Option<&ModeDescription> {
+        self.modes.iter().find(|mode| mode.mode_id == mode_id)
+    }
+
+    // Modes are looked up by name when their id is not known in advance
+    pub fn mode_by_name(&self, name: &str) -> Option<&ModeDescription> {
+        self.modes.iter().find(|mode| mode.name == name)
+    }
+}
+
+impl fmt::Display for PortDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.device {
+            Some(device) => match device.io_type {
+                Some(io_type) => writeln!(f, "Port {}: {:?} ({})", self.port_id, io_type, device.io_type_id)?,
+                None => writeln!(f, "Port {}: io type {}", self.port_id, device.io_type_id)?,
+            },
+            None => writeln!(f, "Port {}", self.port_id)?,
+        }
+        writeln!(f, "  capabilities: {:?}", self.capabilities)?;
+        for mode in self.modes.iter() {
+            writeln!(f, "  {}", mode)?;
+        }
+        for (i, combination) in self.combinations.iter().enumerate() {
+            writeln!(f, "  combination {}: modes {:?}", i, combination.modes())?;
+        }
+        Ok(())
+    }
+}
+
+
+
+/***************************************/
+/************** PortValue **************/
+/***************************************/
+
+// A value of a port, one item per dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortValue {
+    pub port_id:    u8,
+    pub mode_id:    u8,
+    pub raw:        Vec<i32>,   // Float datasets are truncated here - see si
+    pub pct:        Vec<f32>,
+    pub si:         Vec<f32>,
+    pub symbol:     String,
+}
@@ -0,0 +1,273 @@
+// The layer beneath the Communicator.
+// The Communicator speaks in LEGO frames ([msg_len, hub_id, msg_type, ...]).
+// Moving these frames to and from the hub is the job of a Transport:
+// BtleTransport is the real thing, InMemoryTransport is a channel pair meant for tests and simulations.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use btleplug::api::{Peripheral as _, Characteristic, WriteType};
+use btleplug::platform::Peripheral;
+
+use anyhow::{Result, anyhow};
+use tokio::select;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::{BroadcastStream, UnboundedReceiverStream};
+
+// How often BtleTransport checks the connection while waiting for notifications
+const BTLE_CONNECTION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+
+    // Write a single (already encoded) frame to the hub
+    async fn write(&self, frame: &[u8]) -> Result<()>;
+
+    // All upstream frames, as they arrive. The stream ends when the link drops.
+    async fn upstream(&self) -> Result<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>>;
+
+    // Transports which can't tell are always connected
+    async fn is_connected(&self) -> bool {
+        true
+    }
+
+    // Connects again after the link dropped. upstream() has to be called again afterwards.
+    async fn reconnect(&self) -> Result<()> {
+        Err(anyhow!("This transport can't reconnect"))
+    }
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        (**self).write(frame).await
+    }
+
+    async fn upstream(&self) -> Result<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>> {
+        (**self).upstream().await
+    }
+
+    async fn is_connected(&self) -> bool {
+        (**self).is_connected().await
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        (**self).reconnect().await
+    }
+}
+
+
+
+/***************************************/
+/************ BtleTransport ************/
+/***************************************/
+
+pub struct BtleTransport {
+    peripheral:     Peripheral,
+    characteristic: Characteristic,
+}
+
+impl BtleTransport {
+    pub async fn new(peripheral: Peripheral) -> Result<Self> {
+        peripheral.discover_services().await?;
+
+        // The first characteristic of the first service - LEGO hubs have just the one
+        let characteristic = peripheral.services()
+            .into_iter()
+            .next()
+            .and_then(|service| service.characteristics.into_iter().next())
+            .ok_or_else(|| anyhow!("No characteristic found on the peripheral"))?;
+        Ok(Self { peripheral, characteristic })
+    }
+
+    pub fn peripheral(&self) -> &Peripheral {
+        &self.peripheral
+    }
+}
+
+#[async_trait]
+impl Transport for BtleTransport {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        let res = self.peripheral.write(
+            &self.characteristic,
+            frame,
+            WriteType::WithResponse).await;
+
+        if res.is_err() {
+            Err(anyhow!("Couldn't send the message"))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn upstream(&self) -> Result<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>> {
+        self.peripheral.subscribe(&self.characteristic).await?;
+        let mut notifications = self.peripheral.notifications().await?;
+        // Notifications don't always end with the connection, so it is checked every now and then
+        let peripheral = self.peripheral.clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut check = time::interval(BTLE_CONNECTION_CHECK_INTERVAL);
+            loop {
+                select! {
+                    notification = notifications.next() => match notification {
+                        Some(notification) => if tx.send(notification.value).is_err() { return },
+                        None => return,
+                    },
+                    _ = check.tick() => if !peripheral.is_connected().await.unwrap_or(false) { return },
+                    _ = tx.closed() => return,
+                }
+            }
+        });
+        Ok(Box::pin(UnboundedReceiverStream::new(rx)))
+    }
+
+    async fn is_connected(&self) -> bool {
+        self.peripheral.is_connected().await.unwrap_or(false)
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        if !self.peripheral.is_connected().await? {
+            self.peripheral.connect().await?;
+        }
+        self.peripheral.discover_services().await?;
+        Ok(())
+    }
+}
+
+
+
+/***************************************/
+/********** InMemoryTransport **********/
+/***************************************/
+
+// Upstream frames go through a broadcast channel, so frames sent before anyone called upstream()
+// are lost - exactly like BLE notifications.
+const IN_MEMORY_NOTIFICATION_CAPACITY: usize = 256;
+
+pub struct InMemoryTransport {
+    downstream_tx:  mpsc::UnboundedSender<Vec<u8>>,
+    notify_tx:      broadcast::Sender<Vec<u8>>,
+    link:           Arc<InMemoryLink>,
+}
+
+// The "hub" end of an InMemoryTransport.
+// Whatever the Communicator writes is received here, and whatever is sent from here is seen as upstream traffic.
+pub struct InMemoryPeer {
+    downstream_rx:  mpsc::UnboundedReceiver<Vec<u8>>,
+    notify_tx:      broadcast::Sender<Vec<u8>>,
+    link:           Arc<InMemoryLink>,
+}
+
+// Shared by both ends, so the peer can play a hub going out of range
+struct InMemoryLink {
+    connected:  watch::Sender<bool>,
+    reachable:  AtomicBool,     // Whether reconnect() succeeds
+}
+
+impl InMemoryTransport {
+    pub fn new() -> (Self, InMemoryPeer) {
+        let (downstream_tx, downstream_rx) = mpsc::unbounded_channel();
+        let (notify_tx, _) = broadcast::channel(IN_MEMORY_NOTIFICATION_CAPACITY);
+        let link = Arc::new(InMemoryLink {
+            connected:  watch::channel(true).0,
+            reachable:  AtomicBool::new(true),
+        });
+        (
+            Self {
+                downstream_tx,
+                notify_tx: notify_tx.clone(),
+                link: link.clone(),
+            },
+            InMemoryPeer {
+                downstream_rx,
+                notify_tx,
+                link,
+            }
+        )
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        if !*self.link.connected.borrow() {
+            return Err(anyhow!("Couldn't send the message"));
+        }
+        self.downstream_tx
+            .send(frame.to_vec())
+            .map_err(|_| anyhow!("Couldn't send the message"))
+    }
+
+    async fn upstream(&self) -> Result<Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>> {
+        let mut connected = self.link.connected.subscribe();
+        if !*connected.borrow_and_update() {
+            return Err(anyhow!("Not connected"));
+        }
+        let mut frames = BroadcastStream::new(self.notify_tx.subscribe());
+        // Forwards the frames until the peer disconnects
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                select! {
+                    frame = frames.next() => match frame {
+                        Some(Ok(frame)) => if tx.send(frame).is_err() { return },
+                        Some(Err(_)) => continue,
+                        None => return,
+                    },
+                    changed = connected.changed() => if changed.is_err() || !*connected.borrow() { return },
+                    _ = tx.closed() => return,
+                }
+            }
+        });
+        Ok(Box::pin(UnboundedReceiverStream::new(rx)))
+    }
+
+    async fn is_connected(&self) -> bool {
+        *self.link.connected.borrow()
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        if !self.link.reachable.load(Ordering::SeqCst) {
+            return Err(anyhow!("The in-memory peer is unreachable"));
+        }
+        self.link.connected.send_replace(true);
+        Ok(())
+    }
+}
+
+impl InMemoryPeer {
+    // Send an upstream frame, as if the hub has sent it
+    pub fn send(&self, frame: Vec<u8>) -> Result<()> {
+        self.notify_tx
+            .send(frame)
+            .map(|_| ())
+            .map_err(|_| anyhow!("Nobody listens to the in-memory transport"))
+    }
+
+    // Wait for the next frame written by the Communicator
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        self.downstream_rx.recv().await
+    }
+
+    // Take the next frame written by the Communicator, if there is one
+    pub fn try_recv(&mut self) -> Option<Vec<u8>> {
+        self.downstream_rx.try_recv().ok()
+    }
+
+    // Drops the link, like a hub going out of range
+    pub fn disconnect(&self) {
+        self.link.connected.send_replace(false);
+    }
+
+    // Whether the transport's reconnect() succeeds. It does by default.
+    pub fn set_reachable(&self, reachable: bool) {
+        self.link.reachable.store(reachable, Ordering::SeqCst);
+    }
+}
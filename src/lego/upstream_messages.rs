@@ -0,0 +1,703 @@
+// Typed representation of every message the hub may send us (Upstream).
+// UpstreamMessage::parse() takes a whole frame, as received from the transport, and never panics:
+// a short or otherwise malformed frame results in an error describing what was missing.
+// See message_types for the list of these messages.
+
+use anyhow::{Result, bail};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+use super::MessageTypes;
+use super::consts::{
+    LegoErrorTypes,
+    PortType,
+};
+use super::message_parameters::{
+    HubActionsTypes,
+    HubAlertsOperations,
+    HubAlertsTypes,
+    HubPropertiesOperations,
+    HubPropertiesProperties,
+    PortInformationType,
+    PortModeInformationType,
+};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpstreamMessage {
+    HubProperties(HubPropertiesMessage),
+    HubActions(HubActionsMessage),
+    HubAlerts(HubAlertsMessage),
+    HubAttachedIO(HubAttachedIOMessage),
+    GenericErrorMessages(GenericErrorMessage),
+    FWLockStatus(FWLockStatusMessage),
+    PortInformation(PortInformationMessage),
+    PortModeInformation(PortModeInformationMessage),
+    PortValueSingle(PortValueSingleMessage),
+    PortValueCombinedMode(PortValueCombinedModeMessage),
+    PortInputFormatSingle(PortInputFormatSingleMessage),
+    PortInputFormatCombinedMode(PortInputFormatCombinedModeMessage),
+    PortOutputCommandFeedback(PortOutputCommandFeedbackMessage),
+}
+
+impl UpstreamMessage {
+    pub fn parse(frame: &[u8]) -> Result<Self> {
+        let mut reader = FrameReader::new(frame);
+
+        // Common header: [msg_len (1 or 2 bytes), hub_id, msg_type]
+        let first = reader.u8("message length")?;
+        let length = if first & 0x80 == 0x80 {
+            let second = reader.u8("message length (2nd byte)")?;
+            (first & 0x7f) as usize | (second as usize) << 7
+        } else {
+            first as usize
+        };
+        if length != frame.len() {
+            bail!("[Error] Message length is {} but {} bytes were received", length, frame.len())
+        }
+        _ = reader.u8("hub id")?;
+        let msg_type_id = reader.u8("message type")?;
+        let msg_type: MessageTypes = match FromPrimitive::from_u8(msg_type_id) {
+            Some(x) => x,
+            None => bail!("[Error] Unknown message type {:#04x}", msg_type_id),
+        };
+
+        let msg = match msg_type {
+            MessageTypes::HubProperties => UpstreamMessage::HubProperties(HubPropertiesMessage::parse(&mut reader)?),
+            MessageTypes::HubActions => UpstreamMessage::HubActions(HubActionsMessage::parse(&mut reader)?),
+            MessageTypes::HubAlerts => UpstreamMessage::HubAlerts(HubAlertsMessage::parse(&mut reader)?),
+            MessageTypes::HubAttachedIO => UpstreamMessage::HubAttachedIO(HubAttachedIOMessage::parse(&mut reader)?),
+            MessageTypes::GenericErrorMessages => UpstreamMessage::GenericErrorMessages(GenericErrorMessage::parse(&mut reader)?),
+            MessageTypes::FWLockStatus => UpstreamMessage::FWLockStatus(FWLockStatusMessage::parse(&mut reader)?),
+            MessageTypes::PortInformation => UpstreamMessage::PortInformation(PortInformationMessage::parse(&mut reader)?),
+            MessageTypes::PortModeInformation => UpstreamMessage::PortModeInformation(PortModeInformationMessage::parse(&mut reader)?),
+            MessageTypes::PortValueSingle => UpstreamMessage::PortValueSingle(PortValueSingleMessage::parse(&mut reader)?),
+            MessageTypes::PortValueCombinedMode => UpstreamMessage::PortValueCombinedMode(PortValueCombinedModeMessage::parse(&mut reader)?),
+            MessageTypes::PortInputFormatSingle => UpstreamMessage::PortInputFormatSingle(PortInputFormatSingleMessage::parse(&mut reader)?),
+            MessageTypes::PortInputFormatCombinedMode => UpstreamMessage::PortInputFormatCombinedMode(PortInputFormatCombinedModeMessage::parse(&mut reader)?),
+            MessageTypes::PortOutputCommandFeedback => UpstreamMessage::PortOutputCommandFeedback(PortOutputCommandFeedbackMessage::parse(&mut reader)?),
+            _ => bail!("[Error] {:?} is not an upstream message", msg_type),
+        };
+        Ok(msg)
+    }
+
+    pub fn message_type(&self) -> MessageTypes {
+        match self {
+            UpstreamMessage::HubProperties(_) => MessageTypes::HubProperties,
+            UpstreamMessage::HubActions(_) => MessageTypes::HubActions,
+            UpstreamMessage::HubAlerts(_) => MessageTypes::HubAlerts,
+            UpstreamMessage::HubAttachedIO(_) => MessageTypes::HubAttachedIO,
+            UpstreamMessage::GenericErrorMessages(_) => MessageTypes::GenericErrorMessages,
+            UpstreamMessage::FWLockStatus(_) => MessageTypes::FWLockStatus,
+            UpstreamMessage::PortInformation(_) => MessageTypes::PortInformation,
+            UpstreamMessage::PortModeInformation(_) => MessageTypes::PortModeInformation,
+            UpstreamMessage::PortValueSingle(_) => MessageTypes::PortValueSingle,
+            UpstreamMessage::PortValueCombinedMode(_) => MessageTypes::PortValueCombinedMode,
+            UpstreamMessage::PortInputFormatSingle(_) => MessageTypes::PortInputFormatSingle,
+            UpstreamMessage::PortInputFormatCombinedMode(_) => MessageTypes::PortInputFormatCombinedMode,
+            UpstreamMessage::PortOutputCommandFeedback(_) => MessageTypes::PortOutputCommandFeedback,
+        }
+    }
+
+    // The port this message relates to (if it is a port related message)
+    pub fn port_id(&self) -> Option<u8> {
+        match self {
+            UpstreamMessage::HubAttachedIO(msg) => Some(msg.port_id),
+            UpstreamMessage::PortInformation(msg) => Some(msg.port_id),
+            UpstreamMessage::PortModeInformation(msg) => Some(msg.port_id),
+            UpstreamMessage::PortValueSingle(msg) => Some(msg.port_id),
+            UpstreamMessage::PortValueCombinedMode(msg) => Some(msg.port_id),
+            UpstreamMessage::PortInputFormatSingle(msg) => Some(msg.port_id),
+            UpstreamMessage::PortInputFormatCombinedMode(msg) => Some(msg.port_id),
+            _ => None,
+        }
+    }
+}
+
+
+
+/***************************************/
+/************* FrameReader *************/
+/***************************************/
+
+// Bounds checked reading of a frame. Each read names the field, so errors are readable.
+pub struct FrameReader<'a> {
+    data:   &'a [u8],
+    pos:    usize,
+}
+
+impl<'a> FrameReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, n: usize, field: &str) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            bail!(
+                "[Error] Malformed message: {} needs {} byte(s) at offset {}, but only {} left",
+                field, n, self.pos, self.remaining()
+            )
+        }
+        let res = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(res)
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        let res = &self.data[self.pos..];
+        self.pos = self.data.len();
+        res
+    }
+
+    pub fn u8(&mut self, field: &str) -> Result<u8> {
+        Ok(self.bytes(1, field)?[0])
+    }
+
+    pub fn i8(&mut self, field: &str) -> Result<i8> {
+        Ok(self.u8(field)? as i8)
+    }
+
+    pub fn u16(&mut self, field: &str) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2, field)?.try_into()?))
+    }
+
+    pub fn i16(&mut self, field: &str) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.bytes(2, field)?.try_into()?))
+    }
+
+    pub fn u32(&mut self, field: &str) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4, field)?.try_into()?))
+    }
+
+    pub fn i32(&mut self, field: &str) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4, field)?.try_into()?))
+    }
+
+    pub fn f32(&mut self, field: &str) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4, field)?.try_into()?))
+    }
+
+    // Reads a value and converts it to one of the protocol enums
+    pub fn enum_u8<T: FromPrimitive>(&mut self, field: &str) -> Result<T> {
+        let value = self.u8(field)?;
+        match FromPrimitive::from_u8(value) {
+            Some(x) => Ok(x),
+            None => bail!("[Error] Malformed message: unknown {} {:#04x}", field, value),
+        }
+    }
+}
+
+
+
+/***************************************/
+/*********** VersionNumber *************/
+/***************************************/
+
+// LEGO encodes versions in an i32:
+// 0MMM mmmm BBBB BBBB bbbb bbbb bbbb bbbb - Major (0-7), minor (0-9), Bug fix (BCD 0-99), Build (BCD 0-9999)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionNumber {
+    pub major:      u8,
+    pub minor:      u8,
+    pub bug_fix:    u8,
+    pub build:      u16,
+}
+
+impl VersionNumber {
+    pub fn from_raw(raw: i32) -> Self {
+        let raw = raw as u32;
+        Self {
+            major:      ((raw >> 28) & 0x7) as u8,
+            minor:      ((raw >> 24) & 0xf) as u8,
+            bug_fix:    bcd_to_decimal((raw >> 16) & 0xff) as u8,
+            build:      bcd_to_decimal(raw & 0xffff) as u16,
+        }
+    }
+}
+
+impl std::fmt::Display for VersionNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{:02}.{:04}", self.major, self.minor, self.bug_fix, self.build)
+    }
+}
+
+fn bcd_to_decimal(mut bcd: u32) -> u32 {
+    let mut res = 0;
+    let mut factor = 1;
+    while bcd > 0 {
+        res += (bcd & 0xf) * factor;
+        factor *= 10;
+        bcd >>= 4;
+    }
+    res
+}
+
+
+
+/***************************************/
+/************ HubProperties ************/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubPropertiesMessage {
+    pub property:   HubPropertiesProperties,
+    pub operation:  HubPropertiesOperations,
+    pub payload:    Vec<u8>,
+}
+
+impl HubPropertiesMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self {
+            property:   reader.enum_u8("hub property")?,
+            operation:  reader.enum_u8("hub property operation")?,
+            payload:    reader.rest().to_vec(),
+        })
+    }
+
+    pub fn value(&self) -> Result<HubPropertyValue> {
+        HubPropertyValue::parse(self.property, &self.payload)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HubPropertyValue {
+    AdvertisingName(String),
+    Button(bool),                       // Pressed?
+    FWVersion(VersionNumber),
+    HWVersion(VersionNumber),
+    RSSI(i8),                           // dBm
+    BatteryVoltage(u8),                 // Percentage
+    BatteryType(BatteryType),
+    ManufacturerName(String),
+    RadioFirmwareVersion(String),
+    LEGOWirelessProtocolVersion(LwpVersion),
+    SystemTypeID(u8),
+    HWNetworkID(u8),
+    PrimaryMACAddress([u8; 6]),
+    SecondaryMACAddress([u8; 6]),
+    HardwareNetworkFamily(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum BatteryType {
+    Normal          = 0x00,
+    Rechargeable    = 0x01,
+}
+
+// Major and minor, each one is BCD encoded byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LwpVersion {
+    pub major:  u8,
+    pub minor:  u8,
+}
+
+impl std::fmt::Display for LwpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl HubPropertyValue {
+    pub fn parse(property: HubPropertiesProperties, payload: &[u8]) -> Result<Self> {
+        let mut reader = FrameReader::new(payload);
+        let value = match property {
+            HubPropertiesProperties::AdvertisingName => HubPropertyValue::AdvertisingName(parse_string(reader.rest())),
+            HubPropertiesProperties::Button => HubPropertyValue::Button(reader.u8("button state")? != 0x00),
+            HubPropertiesProperties::FWVersion => HubPropertyValue::FWVersion(VersionNumber::from_raw(reader.i32("firmware version")?)),
+            HubPropertiesProperties::HWVersion => HubPropertyValue::HWVersion(VersionNumber::from_raw(reader.i32("hardware version")?)),
+            HubPropertiesProperties::RSSI => HubPropertyValue::RSSI(reader.i8("rssi")?),
+            HubPropertiesProperties::BatteryVoltage => HubPropertyValue::BatteryVoltage(reader.u8("battery voltage")?),
+            HubPropertiesProperties::BatteryType => HubPropertyValue::BatteryType(reader.enum_u8("battery type")?),
+            HubPropertiesProperties::ManufacturerName => HubPropertyValue::ManufacturerName(parse_string(reader.rest())),
+            HubPropertiesProperties::RadioFirmwareVersion => HubPropertyValue::RadioFirmwareVersion(parse_string(reader.rest())),
+            HubPropertiesProperties::LEGOWirelessProtocolVersion => {
+                let version = reader.u16("wireless protocol version")?;
+                HubPropertyValue::LEGOWirelessProtocolVersion(LwpVersion {
+                    major: bcd_to_decimal((version >> 8) as u32) as u8,
+                    minor: bcd_to_decimal((version & 0xff) as u32) as u8,
+                })
+            },
+            HubPropertiesProperties::SystemTypeID => HubPropertyValue::SystemTypeID(reader.u8("system type id")?),
+            HubPropertiesProperties::HWNetworkID => HubPropertyValue::HWNetworkID(reader.u8("network id")?),
+            HubPropertiesProperties::PrimaryMACAddress => HubPropertyValue::PrimaryMACAddress(reader.bytes(6, "primary mac address")?.try_into()?),
+            HubPropertiesProperties::SecondaryMACAddress => HubPropertyValue::SecondaryMACAddress(reader.bytes(6, "secondary mac address")?.try_into()?),
+            HubPropertiesProperties::HardwareNetworkFamily => HubPropertyValue::HardwareNetworkFamily(reader.u8("network family")?),
+        };
+        Ok(value)
+    }
+}
+
+// Strings are not always null terminated, and sometimes they are padded with nulls
+fn parse_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_end_matches(char::from(0))
+        .to_string()
+}
+
+
+/***************************************/
+/************* HubActions **************/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubActionsMessage {
+    pub action_type:    HubActionsTypes,
+}
+
+impl HubActionsMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self { action_type: reader.enum_u8("hub action type")? })
+    }
+}
+
+
+/***************************************/
+/************** HubAlerts **************/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubAlertsMessage {
+    pub alert_type:     HubAlertsTypes,
+    pub operation:      HubAlertsOperations,
+    pub alert:          bool,       // Payload: 0x00 - Status OK, 0xFF - Alert!
+}
+
+impl HubAlertsMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self {
+            alert_type: reader.enum_u8("hub alert type")?,
+            operation:  reader.enum_u8("hub alert operation")?,
+            alert:      reader.u8("hub alert payload")? != 0x00,
+        })
+    }
+}
+
+
+/***************************************/
+/************ HubAttachedIO ************/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubAttachedIOMessage {
+    pub port_id:    u8,
+    pub event:      AttachedIOEvent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachedIOEvent {
+    DetachedIO,
+    AttachedIO {
+        io_type_id:         u16,
+        io_type:            Option<PortType>,
+        hardware_revision:  VersionNumber,
+        software_revision:  VersionNumber,
+    },
+    AttachedVirtualIO {
+        io_type_id:         u16,
+        io_type:            Option<PortType>,
+        port_a:             u8,
+        port_b:             u8,
+    },
+}
+
+impl HubAttachedIOMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let port_id = reader.u8("port id")?;
+        let event = match reader.u8("attached io event")? {
+            0x00 => AttachedIOEvent::DetachedIO,
+            0x01 => {
+                let io_type_id = reader.u16("io type id")?;
+                AttachedIOEvent::AttachedIO {
+                    io_type_id,
+                    io_type:            FromPrimitive::from_u16(io_type_id),
+                    hardware_revision:  VersionNumber::from_raw(reader.i32("hardware revision")?),
+                    software_revision:  VersionNumber::from_raw(reader.i32("software revision")?),
+                }
+            },
+            0x02 => {
+                let io_type_id = reader.u16("io type id")?;
+                AttachedIOEvent::AttachedVirtualIO {
+                    io_type_id,
+                    io_type:    FromPrimitive::from_u16(io_type_id),
+                    port_a:     reader.u8("port id A")?,
+                    port_b:     reader.u8("port id B")?,
+                }
+            },
+            x => bail!("[Error] Malformed message: unknown attached io event {:#04x}", x),
+        };
+        Ok(Self { port_id, event })
+    }
+}
+
+
+/***************************************/
+/********* GenericErrorMessages ********/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericErrorMessage {
+    pub command_type_id:    u8,
+    pub command_type:       Option<MessageTypes>,
+    pub error_code:         Option<LegoErrorTypes>,
+}
+
+impl GenericErrorMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let command_type_id = reader.u8("error command type")?;
+        Ok(Self {
+            command_type_id,
+            command_type:   FromPrimitive::from_u8(command_type_id),
+            error_code:     FromPrimitive::from_u8(reader.u8("error code")?),
+        })
+    }
+}
+
+impl std::fmt::Display for GenericErrorMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cmd_str = match self.command_type {
+            Some(x) => format!("{:?}", x),
+            None => "UnknownCommand".to_string(),
+        };
+        let err_str = match self.error_code {
+            Some(x) => format!("{:?}", x),
+            None => "UnknownError".to_string(),
+        };
+        write!(f, "[Error] On command {}: {}", cmd_str, err_str)
+    }
+}
+
+
+/***************************************/
+/************ FWLockStatus *************/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FWLockStatusMessage {
+    pub locked:     bool,
+}
+
+impl FWLockStatusMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self { locked: reader.u8("lock status")? != 0x00 })
+    }
+}
+
+
+/***************************************/
+/*********** PortInformation ***********/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortInformationMessage {
+    pub port_id:    u8,
+    pub info:       PortInformationPayload,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortInformationPayload {
+    ModeInfo {
+        capabilities:       u8,
+        total_mode_count:   u8,
+        input_modes:        u16,
+        output_modes:       u16,
+    },
+    PossibleModeCombinations {
+        combinations:       Vec<u16>,
+    },
+}
+
+impl PortInformationMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let port_id = reader.u8("port id")?;
+        let info_type: PortInformationType = reader.enum_u8("port information type")?;
+        let info = match info_type {
+            PortInformationType::ModeInfo => PortInformationPayload::ModeInfo {
+                capabilities:       reader.u8("capabilities")?,
+                total_mode_count:   reader.u8("total mode count")?,
+                input_modes:        reader.u16("input modes")?,
+                output_modes:       reader.u16("output modes")?,
+            },
+            PortInformationType::PossibleModeCombinations => {
+                let mut combinations = Vec::new();
+                while reader.remaining() > 0 {
+                    combinations.push(reader.u16("mode combination")?);
+                }
+                PortInformationPayload::PossibleModeCombinations { combinations }
+            },
+            PortInformationType::PortValue => bail!("[Error] Port value is replied with PortValueSingle, not PortInformation"),
+        };
+        Ok(Self { port_id, info })
+    }
+}
+
+
+/***************************************/
+/********* PortModeInformation *********/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortModeInformationMessage {
+    pub port_id:    u8,
+    pub mode_id:    u8,
+    pub info_type:  PortModeInformationType,
+    pub payload:    Vec<u8>,
+}
+
+impl PortModeInformationMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self {
+            port_id:    reader.u8("port id")?,
+            mode_id:    reader.u8("mode id")?,
+            info_type:  reader.enum_u8("mode information type")?,
+            payload:    reader.rest().to_vec(),
+        })
+    }
+}
+
+
+/***************************************/
+/*********** PortValueSingle ***********/
+/***************************************/
+
+// The size of the value depends on the mode's value format - so it is kept raw
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortValueSingleMessage {
+    pub port_id:    u8,
+    pub data:       Vec<u8>,
+}
+
+impl PortValueSingleMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let port_id = reader.u8("port id")?;
+        let data = reader.rest().to_vec();
+        if data.is_empty() {
+            bail!("[Error] Malformed message: port value of port {} is empty", port_id)
+        }
+        Ok(Self { port_id, data })
+    }
+}
+
+
+/***************************************/
+/******** PortValueCombinedMode ********/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortValueCombinedModeMessage {
+    pub port_id:            u8,
+    pub mode_pointers:      u16,    // Bit per mode/dataset of the combination
+    pub data:               Vec<u8>,
+}
+
+impl PortValueCombinedModeMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self {
+            port_id:        reader.u8("port id")?,
+            mode_pointers:  reader.u16("mode/dataset pointers")?,
+            data:           reader.rest().to_vec(),
+        })
+    }
+}
+
+
+/***************************************/
+/******** PortInputFormatSingle ********/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortInputFormatSingleMessage {
+    pub port_id:                u8,
+    pub mode_id:                u8,
+    pub delta:                  u32,
+    pub notifications_enabled:  bool,
+}
+
+impl PortInputFormatSingleMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        Ok(Self {
+            port_id:                reader.u8("port id")?,
+            mode_id:                reader.u8("mode id")?,
+            delta:                  reader.u32("delta interval")?,
+            notifications_enabled:  reader.u8("notification enabled")? != 0x00,
+        })
+    }
+}
+
+
+/***************************************/
+/***** PortInputFormatCombinedMode *****/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortInputFormatCombinedModeMessage {
+    pub port_id:                u8,
+    pub combination_index:      u8,
+    pub multi_update_enabled:   bool,
+    pub mode_pointers:          u16,
+}
+
+impl PortInputFormatCombinedModeMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let port_id = reader.u8("port id")?;
+        let control = reader.u8("control")?;
+        Ok(Self {
+            port_id,
+            combination_index:      control & 0x07,
+            multi_update_enabled:   control & 0x80 == 0x80,
+            mode_pointers:          reader.u16("mode/dataset pointers")?,
+        })
+    }
+}
+
+
+/***************************************/
+/****** PortOutputCommandFeedback ******/
+/***************************************/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortOutputCommandFeedbackMessage {
+    pub feedbacks:  Vec<PortOutputFeedback>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortOutputFeedback {
+    pub port_id:                            u8,
+    pub buffer_empty_command_in_progress:   bool,   // Bit 0
+    pub buffer_empty_command_completed:     bool,   // Bit 1
+    pub current_command_discarded:          bool,   // Bit 2
+    pub idle:                               bool,   // Bit 3
+    pub busy_full:                          bool,   // Bit 4
+}
+
+impl PortOutputFeedback {
+    pub fn from_raw(port_id: u8, feedback: u8) -> Self {
+        Self {
+            port_id,
+            buffer_empty_command_in_progress:   feedback & 0x01 == 0x01,
+            buffer_empty_command_completed:     feedback & 0x02 == 0x02,
+            current_command_discarded:          feedback & 0x04 == 0x04,
+            idle:                               feedback & 0x08 == 0x08,
+            busy_full:                          feedback & 0x10 == 0x10,
+        }
+    }
+}
+
+impl PortOutputCommandFeedbackMessage {
+    fn parse(reader: &mut FrameReader) -> Result<Self> {
+        let mut feedbacks = Vec::new();
+        // At least one port. The hub may pack several ports in the same message.
+        loop {
+            let port_id = reader.u8("port id")?;
+            let feedback = reader.u8("feedback")?;
+            feedbacks.push(PortOutputFeedback::from_raw(port_id, feedback));
+            if reader.remaining() == 0 {
+                break;
+            }
+        }
+        Ok(Self { feedbacks })
+    }
+}
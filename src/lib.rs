@@ -9,32 +9,75 @@
 //! Below are the main traits. More located at lego/mod.rs
 //! 
 
-use std::pin::Pin;
+use std::collections::HashMap;
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use async_trait::async_trait;
+use tokio::task::JoinHandle;
 
-use btleplug::api::ValueNotification;
 use hub::{
     PortInfoValueReply, PortInfoModeReply, PortInfoCombinationsReply
 };
 use lego::{
+    attached_io::{
+        AttachedDevice,
+        AttachmentEvent,
+    },
+    CommandHandle,
+    ConnectionEvent,
+    NotificationStream,
+    UpstreamMessage,
+    port_modes::{
+        CombinedModeDecoder,
+        CombinedModeSetup,
+        ModeDescription,
+        PortDescription,
+        PortValue,
+    },
+    upstream_messages::{
+        BatteryType,
+        GenericErrorMessage,
+        HubPropertyValue,
+        LwpVersion,
+        PortOutputFeedback,
+        PortValueCombinedModeMessage,
+        PortValueSingleMessage,
+        VersionNumber,
+    },
     message_parameters::{
+        HubPropertiesProperties,
         PortModeInformationType,
         PortOutputCommandParams, 
         StartupAndCompletionInfo,
     },
     consts:: {
+        Color,
         EndState, 
         Profile,
     }
 };
 use ports::{
+    Accelerometer,
+    ColorDistanceSensor,
+    CurrentSensor,
+    Gyro,
+    Led,
     Motor,
+    MotorState,
+    SyncedMotorPair,
+    TechnicColorSensor,
+    TechnicDistanceSensor,
+    TechnicForceSensor,
+    TiltSensor,
+    Timestamped,
+    Vector3,
+    VoltageSensor,
 };
-use tokio_stream::Stream;
 
+pub mod calibration;
 pub mod connection_manager;
+pub mod fleet;
 pub mod hub;
 pub mod lego;
 pub mod ports;
@@ -47,18 +90,50 @@ pub trait HubType {
     
     async fn shut_down_hub(&self) -> Result<()>;
 
-    async fn get_notification(&self) -> Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>>;
+    // Every decoded upstream message
+    async fn get_notification(&self) -> Result<NotificationStream<UpstreamMessage>>;
+
+    // Value updates of a single port. Make sure to enable its notifications (see setup_port_input_format)
+    async fn get_port_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValueSingleMessage>>;
+
+    // Value updates of a port in combined mode (see setup_port_combined_mode)
+    async fn get_combined_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValueCombinedModeMessage>>;
+
+    // Devices being attached / detached
+    async fn get_attachment_stream(&self) -> Result<NotificationStream<AttachmentEvent>>;
+
+    // What is attached to each port right now
+    async fn get_attached_devices(&self) -> Result<HashMap<u8, AttachedDevice>>;
+
+    // Waits a little (the reply timeout) for the device to show up, in case the hub has just connected
+    async fn get_attached_device(&self, port_id: u8) -> Result<AttachedDevice>;
+
+    // Port Output Command feedback, one item per port
+    async fn get_feedback_stream(&self) -> Result<NotificationStream<PortOutputFeedback>>;
+
+    // Generic error messages sent by the hub
+    async fn get_error_stream(&self) -> Result<NotificationStream<GenericErrorMessage>>;
+
+    // The link dropping and coming back. See Hub::set_reconnect_policy()
+    async fn get_connection_event_stream(&self) -> Result<NotificationStream<ConnectionEvent>>;
 
     async fn get_port_info_value(
         &self, 
         port_id: u8,
     ) -> Result<PortInfoValueReply>;
 
+    // The first dataset of the value, decoded by the format of the port's current mode if it is known
     async fn get_port_info_raw_value(
         &self,
         port_id: u8
     ) -> Result<i32>;
 
+    // The value in the port's current mode (see setup_port_input_format), decoded and scaled to PCT and SI
+    async fn get_port_value(&self, port_id: u8) -> Result<PortValue>;
+
+    // Like get_port_value_stream, decoded and scaled. The mode is the one set when subscribing.
+    async fn get_decoded_port_value_stream(&self, port_id: u8) -> Result<NotificationStream<PortValue>>;
+
     async fn get_port_info_mode(
         &self, 
         port_id: u8,
@@ -76,6 +151,13 @@ pub trait HubType {
         info_type: PortModeInformationType
     ) -> Result<Vec<u8>>;
 
+    // Asks the port about each of its modes - works for devices which aren't known in advance.
+    // Takes a few dozens of requests, so better done once per device.
+    async fn describe_port(&self, port_id: u8) -> Result<PortDescription>;
+
+    // A single mode. Kept per device type, so it's asked for only once.
+    async fn describe_mode(&self, port_id: u8, mode_id: u8) -> Result<ModeDescription>;
+
     async fn setup_port_input_format(
         &self,
         port_id:                u8,
@@ -84,9 +166,145 @@ pub trait HubType {
         enable_notifications:   bool,
     ) -> Result<()>;
 
-    async fn send_output_command(&self, subcommand: PortOutputCommandParams)-> Result<Vec<u8>>;
+    // Locks the port, sets up each of the modes, sets the combination and unlocks the port with multi-update enabled.
+    // The returned decoder splits the values of get_combined_value_stream.
+    async fn setup_port_combined_mode(&self, setup: CombinedModeSetup) -> Result<CombinedModeDecoder>;
+
+    // The handle resolves on the command's completion, if feedback was asked for (see StartupAndCompletionInfo)
+    async fn send_output_command(&self, subcommand: PortOutputCommandParams)-> Result<CommandHandle>;
 
     async fn get_motor(&self, port_id: u8) -> Result<Motor>;
+
+    // The hub's status LED. On a Technic hub it's on TechnicHubPorts::LED, see also get_hub_led.
+    async fn get_led(&self, port_id: u8) -> Result<Led>;
+
+    // The Technic hub's built-in sensors, see TechnicHubPorts
+    async fn get_accelerometer(&self, port_id: u8) -> Result<Accelerometer>;
+
+    async fn get_gyro(&self, port_id: u8) -> Result<Gyro>;
+
+    async fn get_tilt_sensor(&self, port_id: u8) -> Result<TiltSensor>;
+
+    // See ports::BatteryMonitor for watching both
+    async fn get_voltage_sensor(&self, port_id: u8) -> Result<VoltageSensor>;
+
+    async fn get_current_sensor(&self, port_id: u8) -> Result<CurrentSensor>;
+
+    // The built-in devices, on the ports of the hub's kind (see HubKind::ports).
+    // These fail when the kind wasn't advertised - the getters above take the port instead.
+    async fn get_hub_led(&self) -> Result<Led>;
+
+    async fn get_hub_accelerometer(&self) -> Result<Accelerometer>;
+
+    async fn get_hub_gyro(&self) -> Result<Gyro>;
+
+    async fn get_hub_tilt_sensor(&self) -> Result<TiltSensor>;
+
+    async fn get_hub_voltage_sensor(&self) -> Result<VoltageSensor>;
+
+    async fn get_hub_current_sensor(&self) -> Result<CurrentSensor>;
+
+    async fn get_color_distance_sensor(&self, port_id: u8) -> Result<ColorDistanceSensor>;
+
+    // SPIKE Prime sensors
+    async fn get_technic_color_sensor(&self, port_id: u8) -> Result<TechnicColorSensor>;
+
+    async fn get_technic_distance_sensor(&self, port_id: u8) -> Result<TechnicDistanceSensor>;
+
+    async fn get_technic_force_sensor(&self, port_id: u8) -> Result<TechnicForceSensor>;
+
+    // Returns the id of the new virtual port. Both ports have to be synchronizable (e.g. motors of the same type).
+    async fn create_virtual_port(&self, port_a: u8, port_b: u8) -> Result<u8>;
+
+    async fn destroy_virtual_port(&self, port_id: u8) -> Result<()>;
+
+    // Reuses the virtual port of these two motors, if there is one already
+    async fn get_synced_motor_pair(&self, port_a: u8, port_b: u8) -> Result<SyncedMotorPair>;
+}
+
+
+
+// Gets a property and unwraps its value - the variant of HubPropertyValue is named after the property
+macro_rules! hub_property {
+    ($hub:expr, $property:ident) => {
+        match $hub.get_hub_property(HubPropertiesProperties::$property).await? {
+            HubPropertyValue::$property(x) => Ok(x),
+            other => bail!("[Error] Unexpected hub property: {:?}", other),
+        }
+    };
+}
+
+#[async_trait]
+pub trait HubPropertiesType: Sync {
+
+    async fn get_hub_property(&self, property: HubPropertiesProperties) -> Result<HubPropertyValue>;
+
+    // Up to 14 ASCII characters. Stays until reset (or until the hub's firmware is updated).
+    async fn set_advertising_name(&self, name: &str) -> Result<()>;
+
+    async fn reset_advertising_name(&self) -> Result<()>;
+
+    // The hub keeps sending updates of this property until disabled
+    async fn enable_hub_property_updates(
+        &self,
+        property: HubPropertiesProperties,
+    ) -> Result<NotificationStream<HubPropertyValue>>;
+
+    async fn disable_hub_property_updates(&self, property: HubPropertiesProperties) -> Result<()>;
+
+    async fn get_advertising_name(&self) -> Result<String> {
+        hub_property!(self, AdvertisingName)
+    }
+
+    async fn is_button_pressed(&self) -> Result<bool> {
+        hub_property!(self, Button)
+    }
+
+    async fn get_fw_version(&self) -> Result<VersionNumber> {
+        hub_property!(self, FWVersion)
+    }
+
+    async fn get_hw_version(&self) -> Result<VersionNumber> {
+        hub_property!(self, HWVersion)
+    }
+
+    // dBm
+    async fn get_rssi(&self) -> Result<i8> {
+        hub_property!(self, RSSI)
+    }
+
+    // Percentage
+    async fn get_battery_voltage(&self) -> Result<u8> {
+        hub_property!(self, BatteryVoltage)
+    }
+
+    async fn get_battery_type(&self) -> Result<BatteryType> {
+        hub_property!(self, BatteryType)
+    }
+
+    async fn get_manufacturer_name(&self) -> Result<String> {
+        hub_property!(self, ManufacturerName)
+    }
+
+    async fn get_radio_fw_version(&self) -> Result<String> {
+        hub_property!(self, RadioFirmwareVersion)
+    }
+
+    async fn get_lwp_version(&self) -> Result<LwpVersion> {
+        hub_property!(self, LEGOWirelessProtocolVersion)
+    }
+
+    async fn get_system_type_id(&self) -> Result<u8> {
+        hub_property!(self, SystemTypeID)
+    }
+
+    async fn get_primary_mac_address(&self) -> Result<[u8; 6]> {
+        hub_property!(self, PrimaryMACAddress)
+    }
+
+    async fn get_secondary_mac_address(&self) -> Result<[u8; 6]> {
+        hub_property!(self, SecondaryMACAddress)
+    }
 }
 
 
@@ -100,19 +318,19 @@ pub trait MotorType {
         &self, 
         time: i16,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn set_deceleration_time(
         &self,
         time: i16,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn start_power(
         &self, 
         power: i8, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn start_speed(
         &self, 
@@ -120,20 +338,31 @@ pub trait MotorType {
         max_power: i8, 
         use_profile: Profile, 
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
+
+    // The hub keeps the time, so it doesn't drift with BLE latency like start_speed + sleep + stop_motor
+    async fn start_speed_for_time(
+        &self,
+        time_ms: u16,
+        speed: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
 
     async fn stop_motor(
         &self,
         end_state: EndState,
         use_profile: Profile, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn set_abs_position(
         &self, 
         position: i32, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn go_to_abs_position(
         &self, 
@@ -143,7 +372,7 @@ pub trait MotorType {
         end_state: EndState,
         use_profile: Profile,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>>;
+    ) -> Result<CommandHandle>;
 
     async fn start_speed_for_deg (
         &self, 
@@ -153,5 +382,142 @@ pub trait MotorType {
         end_state: EndState,
         use_profile: Profile,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>>;
-}
\ No newline at end of file
+    ) -> Result<CommandHandle>;
+
+    // Degrees from the encoder's absolute zero, -180 to 179.
+    // Only motors with an absolute encoder (see ports::ABSOLUTE_MOTOR_TYPES) have it.
+    // Reading switches the port to the APOS mode - while a stream of the port is held, it fails instead.
+    async fn absolute_position(&self) -> Result<i16>;
+
+    // Whether absolute_position() works for this motor - the attached device tells, nothing is sent
+    async fn has_absolute_encoder(&self) -> Result<bool>;
+
+    // Presets the relative position so that 0 is the absolute zero - after that,
+    // go_to_abs_position(0, ...) homes the motor without running into end stops
+    async fn reset_to_absolute_zero(
+        &self,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    // %, negative is counter-clockwise.
+    // Like all reads, fails while a stream of the port in another mode (or state_stream) is held,
+    // since switching the port's mode would stop that stream.
+    async fn speed(&self) -> Result<i8>;
+
+    // Degrees, relative to the last preset (see set_abs_position).
+    // Fails while a stream of another mode is held, like speed.
+    async fn position(&self) -> Result<i32>;
+
+    // Updates of position() as the motor turns at least delta degrees
+    async fn position_stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<i32>>>;
+
+    // Raw value, higher is harder to turn.
+    // Fails while a stream of another mode is held, like speed.
+    async fn load(&self) -> Result<i8>;
+
+    // Snapshots of all of the above at once, through the port's combined mode.
+    // delta is the change of each mode which triggers an update.
+    async fn state_stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<MotorState>>>;
+}
+
+// Two motors behind a virtual port - for tank drives and the like.
+// Each command carries separate values for the A and the B sides.
+// The parameters follow the LWP commands one to one, like MotorType's - with the A and B values that's more than 7.
+#[allow(clippy::too_many_arguments)]
+#[async_trait]
+pub trait SyncedMotorPairType {
+
+    async fn start_power(
+        &self,
+        power_a: i8,
+        power_b: i8,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    async fn start_speed(
+        &self,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    async fn start_speed_for_time(
+        &self,
+        time_ms: u16,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    async fn start_speed_for_deg(
+        &self,
+        degrees: i32,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    async fn go_to_abs_position(
+        &self,
+        abs_pos_a: i32,
+        abs_pos_b: i32,
+        speed: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+
+    async fn stop_motors(
+        &self,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle>;
+}
+
+
+#[async_trait]
+pub trait LedType {
+
+    async fn set_color(&self, color: Color) -> Result<()>;
+
+    async fn set_rgb(&self, red: u8, green: u8, blue: u8) -> Result<()>;
+
+    // Half a period on, half off, in a task of its own.
+    // Blinks forever if there is no count - abort the task to stop it (dropping the handle doesn't).
+    fn blink(&self, color: Color, period: Duration, count: Option<u32>) -> JoinHandle<Result<()>>;
+
+    // Gradually moves from one (red, green, blue) to another, in a task of its own
+    fn fade(&self, from: (u8, u8, u8), to: (u8, u8, u8), duration: Duration) -> JoinHandle<Result<()>>;
+}
+
+
+#[async_trait]
+pub trait ThreeAxisSensorType {
+
+    // A single reading. Switches the port to the sensor's mode if needed.
+    async fn read(&self) -> Result<Vector3>;
+
+    // delta is the change (in raw units) which triggers an update
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<Vector3>>>;
+}
+
+
+#[async_trait]
+pub trait ScalarSensorType {
+
+    // In the SI unit of the sensor's mode. Switches the port to that mode if needed.
+    async fn read(&self) -> Result<f32>;
+
+    // delta is the change (in raw units) which triggers an update
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<f32>>>;
+}
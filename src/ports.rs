@@ -1,6 +1,10 @@
 // Dealing with all ports types and actions
-use anyhow::{Result, Ok};
+use std::cmp::Reverse;
+use std::time::Instant;
+
+use anyhow::{Result, Ok, bail};
 use async_trait::async_trait;
+use tokio_stream::StreamExt;
 
 
 use crate::{
@@ -13,38 +17,181 @@ use crate::{
             SetAccTimePayload,
             SetDecTimePayload,
             StartSpeedPayload,
+            StartSpeedForTimePayload,
             StartSpeedForDegreesPayload,
             GotoAbsolutePositionPayload,
             WriteDirectModeDataPayload,
             SetAbsolutePositionPayload,
             WriteDirectModeDataCommands, 
             StartPowerPayload,
+            StartPowerSyncPayload,
+            StartSpeedSyncPayload,
+            StartSpeedForTimeSyncPayload,
+            StartSpeedForDegreesSyncPayload,
+            GotoAbsolutePositionSyncPayload,
         }, 
         SubcommandType, 
+        CommandHandle,
+        NotificationStream,
+        upstream_messages::FrameReader,
+        port_modes::CombinedModeSetup,
         consts::{
             PortType,
             Profile,
             MotorModes,
             EndState,
         },
-    }, MotorType, HubType
+    }, MotorType, HubType, SyncedMotorPairType
+};
+
+mod led;
+mod imu;
+mod power;
+mod color_distance;
+mod spike;
+
+pub use led::Led;
+pub use imu::{
+    Accelerometer,
+    Gyro,
+    TiltSensor,
+    Vector3,
+};
+pub use power::{
+    BatteryEvent,
+    BatteryMonitor,
+    BatteryThresholds,
+    CurrentSensor,
+    VoltageSensor,
+};
+pub use color_distance::{
+    ColorDistanceSensor,
+    RawRgb,
 };
+pub use spike::{
+    ForceSensorEvent,
+    Hsv,
+    RawRgbI,
+    TechnicColorSensor,
+    TechnicDistanceSensor,
+    TechnicForceSensor,
+};
+
+
+// A sensor reading, along with the time it was received
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timestamped<T> {
+    pub timestamp:  Instant,
+    pub value:      T,
+}
+
+impl<T> Timestamped<T> {
+    pub fn now(value: T) -> Self {
+        Self {
+            timestamp: Instant::now(),
+            value,
+        }
+    }
+}
+
+// Single valued sensors: the first dataset of the mode, in its SI unit (see HubType::describe_mode)
+pub(crate) async fn read_si(hub: &Hub, port_id: u8, mode: u8) -> Result<f32> {
+    hub.ensure_read_mode(port_id, mode).await?;
+    let value = hub.get_port_value(port_id).await?;
+    match value.si.first() {
+        Some(si) => Ok(*si),
+        None => bail!("[Error] Port {} sent an empty value", port_id),
+    }
+}
+
+pub(crate) async fn si_stream(hub: &Hub, port_id: u8, mode: u8, delta: u32) -> Result<NotificationStream<Timestamped<f32>>> {
+    // The mode is set before subscribing, so values are decoded by it - and before enabling notifications,
+    // so the first one isn't missed
+    hub.ensure_input_mode(port_id, mode).await?;
+    let values = hub.get_decoded_port_value_stream(port_id).await?;
+    hub.setup_port_input_format(port_id, mode, delta, true).await?;
+    Ok(Box::pin(values.filter_map(|value| value.si.first().copied().map(Timestamped::now))))
+}
+
+// Sensors which know their own format: the raw data of the mode, decoded by the given function
+pub(crate) async fn read_decoded<T>(hub: &Hub, port_id: u8, mode: u8, decode: fn(&[u8]) -> Result<T>) -> Result<T> {
+    hub.ensure_read_mode(port_id, mode).await?;
+    let reply = hub.get_port_info_value(port_id).await?;
+    decode(&reply.data)
+}
+
+pub(crate) async fn decoded_stream<T: Send + 'static>(
+    hub: &Hub,
+    port_id: u8,
+    mode: u8,
+    delta: u32,
+    decode: fn(&[u8]) -> Result<T>,
+) -> Result<NotificationStream<Timestamped<T>>> {
+    // Subscribing first - the hub sends the current value right away
+    let values = hub.get_port_value_stream(port_id).await?;
+    hub.setup_port_input_format(port_id, mode, delta, true).await?;
+    Ok(Box::pin(values.filter_map(move |msg| decode(&msg.data).ok().map(Timestamped::now))))
+}
+
+// Output modes (e.g. LEDs) take direct mode data, once the port is in that mode
+pub(crate) async fn write_direct(hub: &Hub, port_id: u8, mode: u8, payload: WriteDirectModeDataCommands) -> Result<()> {
+    hub.ensure_input_mode(port_id, mode).await?;
+    _ = hub.send_output_command(
+        PortOutputCommandParams {
+            port_id,
+            start_up_info:  StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction,
+            subcommand_id:  SubcommandType::WriteDirectModeData,
+            payload:        SubcommandPayload::WriteDirectModeData(
+                WriteDirectModeDataPayload {
+                    mode,
+                    payload,
+                }
+            ),
+        }
+    ).await?;
+    Ok(())
+}
 
 
-pub const MOTOR_TYPES: [PortType; 3] = [
+pub const MOTOR_TYPES: [PortType; 7] = [
     PortType::TechnicLargeLinearMotor,
     PortType::TechnicXlargeLinearMotor,
     PortType::TrainMotor,
+    PortType::TechnicMediumAngularMotor,
+    PortType::TechnicLargeAngularMotor,
+    PortType::TechnicMediumAngularMotorGrey,
+    PortType::TechnicLargeAngularMotorGrey,
 ];
 
+// Motors with an absolute encoder (the APOS mode)
+pub const ABSOLUTE_MOTOR_TYPES: [PortType; 6] = [
+    PortType::TechnicLargeLinearMotor,
+    PortType::TechnicXlargeLinearMotor,
+    PortType::TechnicMediumAngularMotor,
+    PortType::TechnicLargeAngularMotor,
+    PortType::TechnicMediumAngularMotorGrey,
+    PortType::TechnicLargeAngularMotorGrey,
+];
+
+
+// A motor's readings at one point in time. See MotorType::state_stream()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotorState {
+    pub speed:      i8,             // %
+    pub position:   i32,            // Degrees, relative
+    pub apos:       Option<i16>,    // Degrees, absolute - if the motor has an absolute encoder
+    pub load:       Option<i8>,     // If the motor can combine it with the rest
+}
+
 
-pub struct Motor<'a> {
-    pub hub:        &'a Hub,
+// Devices hold their own clone of the hub, so they can be moved into spawned tasks
+pub struct Motor {
+    pub hub:        Hub,
     pub port_id:    u8,
 }
 
-impl<'a> Motor<'a> {
-    pub fn new(hub: &'a Hub, port_id: u8) -> Result<Self> {
+impl Motor {
+    pub fn new(hub: Hub, port_id: u8) -> Result<Self> {
         Ok(
             Self {
                 hub,
@@ -90,13 +237,13 @@ impl<'a> Motor<'a> {
 }
 
 #[async_trait]
-impl<'a> MotorType for Motor<'a> {
+impl MotorType for Motor {
 
     async fn set_acceleration_time(
         &self, 
         time: i16,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         self.hub.send_output_command(
             self.get_ouput_command_params(
                 SubcommandType::SetAccTime,
@@ -113,7 +260,7 @@ impl<'a> MotorType for Motor<'a> {
         &self,
         time: i16,
         start_up_info: StartupAndCompletionInfo,
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         self.hub.send_output_command(
             self.get_ouput_command_params(
                 SubcommandType::SetDecTime,
@@ -130,7 +277,7 @@ impl<'a> MotorType for Motor<'a> {
         &self, 
         power: i8, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {        
+    ) -> Result<CommandHandle> {        
         self.hub.send_output_command(
             self.get_wdm_ouput_command_params(
                 MotorModes::Power, 
@@ -150,7 +297,7 @@ impl<'a> MotorType for Motor<'a> {
         max_power: i8, 
         use_profile: Profile, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         self.hub.send_output_command(
             self.get_ouput_command_params(
                 SubcommandType::StartSpeed,
@@ -165,12 +312,37 @@ impl<'a> MotorType for Motor<'a> {
         )).await
     }
 
+    async fn start_speed_for_time(
+        &self,
+        time_ms: u16,
+        speed: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::StartSpeedForTime,
+                SubcommandPayload::StartSpeedForTime(
+                    StartSpeedForTimePayload {
+                        time: time_ms,
+                        speed,
+                        max_power,
+                        end_state,
+                        use_profile,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
     async fn stop_motor(
         &self,
         end_state: EndState,
         use_profile: Profile, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         // Below distinction is dictated by the Docs
         match end_state {
             EndState::HOLD => self.start_speed(0, 0, use_profile, start_up_info).await,
@@ -184,7 +356,7 @@ impl<'a> MotorType for Motor<'a> {
         &self, 
         position: i32, 
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {        
+    ) -> Result<CommandHandle> {        
         self.hub.send_output_command(
             self.get_wdm_ouput_command_params(
                 MotorModes::Pos, 
@@ -206,7 +378,7 @@ impl<'a> MotorType for Motor<'a> {
         end_state: EndState,
         use_profile: Profile,
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         self.hub.send_output_command(
             self.get_ouput_command_params(
                 SubcommandType::GotoAbsolutePosition,
@@ -231,7 +403,7 @@ impl<'a> MotorType for Motor<'a> {
         end_state: EndState,
         use_profile: Profile,
         start_up_info: StartupAndCompletionInfo
-    ) -> Result<Vec<u8>> {
+    ) -> Result<CommandHandle> {
         self.hub.send_output_command(
             self.get_ouput_command_params(
                 SubcommandType::StartSpeedForDegrees,
@@ -247,4 +419,267 @@ impl<'a> MotorType for Motor<'a> {
                 start_up_info
         )).await
     }
-}
\ No newline at end of file
+
+    async fn absolute_position(&self) -> Result<i16> {
+        _ = self.hub.expect_device(self.port_id, &ABSOLUTE_MOTOR_TYPES, "motor with an absolute encoder").await?;
+        read_decoded(&self.hub, self.port_id, MotorModes::Apos as u8, |data| FrameReader::new(data).i16("absolute position")).await
+    }
+
+    async fn has_absolute_encoder(&self) -> Result<bool> {
+        let device = self.hub.get_attached_device(self.port_id).await?;
+        Ok(device.io_type.is_some_and(|io_type| ABSOLUTE_MOTOR_TYPES.contains(&io_type)))
+    }
+
+    async fn reset_to_absolute_zero(
+        &self,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let absolute = self.absolute_position().await?;
+        self.set_abs_position(absolute as i32, start_up_info).await
+    }
+
+    async fn speed(&self) -> Result<i8> {
+        read_decoded(&self.hub, self.port_id, MotorModes::Speed as u8, |data| FrameReader::new(data).i8("speed")).await
+    }
+
+    async fn position(&self) -> Result<i32> {
+        read_decoded(&self.hub, self.port_id, MotorModes::Pos as u8, |data| FrameReader::new(data).i32("position")).await
+    }
+
+    async fn position_stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<i32>>> {
+        decoded_stream(&self.hub, self.port_id, MotorModes::Pos as u8, delta, |data| FrameReader::new(data).i32("position")).await
+    }
+
+    async fn load(&self) -> Result<i8> {
+        read_decoded(&self.hub, self.port_id, MotorModes::Load as u8, |data| FrameReader::new(data).i8("load")).await
+    }
+
+    async fn state_stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<MotorState>>> {
+        let wanted = [MotorModes::Speed, MotorModes::Pos, MotorModes::Apos, MotorModes::Load].map(|mode| mode as u8);
+        let combinations = self.hub.get_port_info_combinations(self.port_id).await?.combinations;
+        // The first combination with the most of the wanted modes, as long as speed and position are in
+        let best = combinations.iter()
+            .enumerate()
+            .map(|(index, combination)| (index, wanted.iter().copied().filter(|mode| combination.contains(*mode)).collect::<Vec<u8>>()))
+            .filter(|(_, modes)| modes.contains(&(MotorModes::Speed as u8)) && modes.contains(&(MotorModes::Pos as u8)))
+            .min_by_key(|(_, modes)| Reverse(modes.len()));
+        let (index, modes) = match best {
+            Some(best) => best,
+            None => bail!("[Error] Port {} can't combine speed and position", self.port_id),
+        };
+
+        let mut setup = CombinedModeSetup::new(self.port_id).combination_index(index as u8);
+        for mode in modes {
+            setup = setup.mode(mode, delta);
+        }
+        // Subscribing first - the hub sends all values once the setup is done
+        let values = self.hub.get_combined_value_stream(self.port_id).await?;
+        let decoder = self.hub.setup_combined_mode(setup, &combinations).await?;
+
+        // Updates may carry only the modes which changed, so the latest of each is kept
+        let (mut speed, mut position, mut apos, mut load) = (None, None, None, None);
+        Ok(Box::pin(values.filter_map(move |msg| {
+            for value in decoder.decode(&msg).ok()? {
+                let raw = value.value.as_i32();
+                match value.mode {
+                    mode if mode == MotorModes::Speed as u8 => speed = Some(raw as i8),
+                    mode if mode == MotorModes::Pos as u8 => position = Some(raw),
+                    mode if mode == MotorModes::Apos as u8 => apos = Some(raw as i16),
+                    mode if mode == MotorModes::Load as u8 => load = Some(raw as i8),
+                    _ => (),
+                }
+            }
+            Some(Timestamped::now(MotorState {
+                speed:      speed?,
+                position:   position?,
+                apos,
+                load,
+            }))
+        })))
+    }
+}
+
+
+
+// Two motors synchronized behind a virtual port.
+// See HubType::get_synced_motor_pair()
+pub struct SyncedMotorPair {
+    pub hub:        Hub,
+    pub port_id:    u8,     // The virtual port
+    pub port_a:     u8,
+    pub port_b:     u8,
+    pub(crate) swapped: bool,   // The virtual port was set up as (port_b, port_a)
+}
+
+impl SyncedMotorPair {
+    // Removes the virtual port. The motors themselves are still usable one by one.
+    pub async fn release(self) -> Result<()> {
+        self.hub.destroy_virtual_port(self.port_id).await
+    }
+
+    // The virtual port takes its values in the order of its members
+    fn in_port_order<T>(&self, a: T, b: T) -> (T, T) {
+        if self.swapped { (b, a) } else { (a, b) }
+    }
+
+    fn get_ouput_command_params(
+        &self,
+        subcommand_id: SubcommandType,
+        payload: SubcommandPayload,
+        start_up_info: StartupAndCompletionInfo
+    ) -> PortOutputCommandParams {
+        PortOutputCommandParams {
+            port_id: self.port_id,
+            start_up_info,
+            subcommand_id,
+            payload,
+        }
+    }
+}
+
+#[async_trait]
+impl SyncedMotorPairType for SyncedMotorPair {
+
+    async fn start_power(
+        &self,
+        power_a: i8,
+        power_b: i8,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let (power_a, power_b) = self.in_port_order(power_a, power_b);
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::StartPowerSync,
+                SubcommandPayload::StartPowerSync(
+                    StartPowerSyncPayload {
+                        power_a,
+                        power_b,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
+    async fn start_speed(
+        &self,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let (speed_a, speed_b) = self.in_port_order(speed_a, speed_b);
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::StartSpeedSync,
+                SubcommandPayload::StartSpeedSync(
+                    StartSpeedSyncPayload {
+                        speed_a,
+                        speed_b,
+                        max_power,
+                        use_profile,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
+    async fn start_speed_for_time(
+        &self,
+        time_ms: u16,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let (speed_a, speed_b) = self.in_port_order(speed_a, speed_b);
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::StartSpeedForTimeSync,
+                SubcommandPayload::StartSpeedForTimeSync(
+                    StartSpeedForTimeSyncPayload {
+                        time: time_ms,
+                        speed_a,
+                        speed_b,
+                        max_power,
+                        end_state,
+                        use_profile,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
+    async fn start_speed_for_deg(
+        &self,
+        degrees: i32,
+        speed_a: i8,
+        speed_b: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let (speed_a, speed_b) = self.in_port_order(speed_a, speed_b);
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::StartSpeedForDegreesSync,
+                SubcommandPayload::StartSpeedForDegreesSync(
+                    StartSpeedForDegreesSyncPayload {
+                        degrees,
+                        speed_a,
+                        speed_b,
+                        max_power,
+                        end_state,
+                        use_profile,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
+    async fn go_to_abs_position(
+        &self,
+        abs_pos_a: i32,
+        abs_pos_b: i32,
+        speed: i8,
+        max_power: i8,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        let (abs_pos_a, abs_pos_b) = self.in_port_order(abs_pos_a, abs_pos_b);
+        self.hub.send_output_command(
+            self.get_ouput_command_params(
+                SubcommandType::GotoAbsolutePositionSync,
+                SubcommandPayload::GotoAbsolutePositionSync(
+                    GotoAbsolutePositionSyncPayload {
+                        abs_pos_a,
+                        abs_pos_b,
+                        speed,
+                        max_power,
+                        end_state,
+                        use_profile,
+                    }
+                ),
+                start_up_info
+        )).await
+    }
+
+    async fn stop_motors(
+        &self,
+        end_state: EndState,
+        use_profile: Profile,
+        start_up_info: StartupAndCompletionInfo,
+    ) -> Result<CommandHandle> {
+        // Same distinction as for a single motor
+        match end_state {
+            EndState::HOLD => self.start_speed(0, 0, 0, use_profile, start_up_info).await,
+            _ => {
+                self.start_power(end_state as i8, end_state as i8, start_up_info).await
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Color & Distance sensor (88007, PortType::ColorDistanceSensor)
+use anyhow::{Result, bail};
+use num_traits::FromPrimitive;
+
+use crate::{
+    hub::Hub,
+    lego::{
+        NotificationStream,
+        upstream_messages::FrameReader,
+        message_parameters::{
+            WriteDirectModeDataCommands,
+            SendIrCodePayload,
+            SetRgbColorNoPayload,
+        },
+        consts::{
+            Color,
+            ColorDistanceSensorModes,
+        },
+    },
+};
+use super::{
+    Timestamped,
+    decoded_stream,
+    read_decoded,
+    write_direct,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawRgb {
+    pub red:    u16,
+    pub green:  u16,
+    pub blue:   u16,
+}
+
+pub(super) fn decode_color(data: &[u8]) -> Result<Color> {
+    let index = FrameReader::new(data).u8("color")?;
+    match Color::from_u8(index) {
+        Some(color) => Ok(color),
+        None => bail!("[Error] Unknown color index {}", index),
+    }
+}
+
+pub(super) fn decode_u8(data: &[u8]) -> Result<u8> {
+    FrameReader::new(data).u8("value")
+}
+
+fn decode_u32(data: &[u8]) -> Result<u32> {
+    FrameReader::new(data).u32("value")
+}
+
+pub(super) fn decode_rgb(data: &[u8]) -> Result<RawRgb> {
+    let mut reader = FrameReader::new(data);
+    Ok(RawRgb {
+        red:    reader.u16("red")?,
+        green:  reader.u16("green")?,
+        blue:   reader.u16("blue")?,
+    })
+}
+
+
+// The sensor has a single mode at a time - reading or streaming one mode switches the port to it,
+// and ends the streams of the other modes.
+pub struct ColorDistanceSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl ColorDistanceSensor {
+    // Color::None when nothing is in front of the sensor
+    pub async fn color(&self) -> Result<Color> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::Color as u8, decode_color).await
+    }
+
+    pub async fn color_stream(&self) -> Result<NotificationStream<Timestamped<Color>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::Color as u8, 1, decode_color).await
+    }
+
+    // 0 (near) to 10 (far)
+    pub async fn proximity(&self) -> Result<u8> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::Prox as u8, decode_u8).await
+    }
+
+    pub async fn proximity_stream(&self) -> Result<NotificationStream<Timestamped<u8>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::Prox as u8, 1, decode_u8).await
+    }
+
+    // Objects passing in front of the sensor
+    pub async fn count(&self) -> Result<u32> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::Count as u8, decode_u32).await
+    }
+
+    pub async fn count_stream(&self) -> Result<NotificationStream<Timestamped<u32>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::Count as u8, 1, decode_u32).await
+    }
+
+    // %
+    pub async fn reflected_light(&self) -> Result<u8> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::Reflt as u8, decode_u8).await
+    }
+
+    pub async fn reflected_light_stream(&self) -> Result<NotificationStream<Timestamped<u8>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::Reflt as u8, 1, decode_u8).await
+    }
+
+    // %
+    pub async fn ambient_light(&self) -> Result<u8> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::Ambi as u8, decode_u8).await
+    }
+
+    pub async fn ambient_light_stream(&self) -> Result<NotificationStream<Timestamped<u8>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::Ambi as u8, 1, decode_u8).await
+    }
+
+    pub async fn rgb(&self) -> Result<RawRgb> {
+        read_decoded(&self.hub, self.port_id, ColorDistanceSensorModes::RgbI as u8, decode_rgb).await
+    }
+
+    pub async fn rgb_stream(&self) -> Result<NotificationStream<Timestamped<RawRgb>>> {
+        decoded_stream(&self.hub, self.port_id, ColorDistanceSensorModes::RgbI as u8, 1, decode_rgb).await
+    }
+
+    // The sensor's own LED. Only some colors are supported (e.g. red, green, blue, white). Black is off.
+    pub async fn set_led_color(&self, color: Color) -> Result<()> {
+        write_direct(
+            &self.hub,
+            self.port_id,
+            ColorDistanceSensorModes::ColO as u8,
+            WriteDirectModeDataCommands::SetRgbColorNo(
+                SetRgbColorNoPayload {
+                    color,
+                }
+            ),
+        ).await
+    }
+
+    // Sends a Power Functions IR message, e.g. to a PF receiver
+    pub async fn send_ir_code(&self, code: u16) -> Result<()> {
+        write_direct(
+            &self.hub,
+            self.port_id,
+            ColorDistanceSensorModes::IrTx as u8,
+            WriteDirectModeDataCommands::SendIrCode(
+                SendIrCodePayload {
+                    code,
+                }
+            ),
+        ).await
+    }
+}
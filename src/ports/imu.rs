@@ -0,0 +1,139 @@
+// The Technic hub's built-in accelerometer, gyro and tilt sensor.
+// Each reports three signed 16 bit datasets.
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio_stream::StreamExt;
+
+use crate::{
+    hub::Hub,
+    lego::{
+        NotificationStream,
+        upstream_messages::FrameReader,
+        consts::{
+            AccelerometerModes,
+            GyroModes,
+            TiltModes,
+        },
+    }, ThreeAxisSensorType
+};
+use super::{
+    Timestamped,
+    decoded_stream,
+    read_decoded,
+};
+
+// Raw to SI, taken from https://github.com/nathankellenicki/node-poweredup
+const ACCELEROMETER_RAW_PER_MG: f32 = 4.096;
+const GYRO_DPS_PER_RAW: f32 = 7.0 / 400.0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector3 {
+    pub x:  f32,
+    pub y:  f32,
+    pub z:  f32,
+}
+
+fn read_axes(data: &[u8]) -> Result<[f32; 3]> {
+    let mut reader = FrameReader::new(data);
+    Ok([
+        reader.i16("first axis")? as f32,
+        reader.i16("second axis")? as f32,
+        reader.i16("third axis")? as f32,
+    ])
+}
+
+fn decode_acceleration(data: &[u8]) -> Result<Vector3> {
+    let [x, y, z] = read_axes(data)?;
+    Ok(Vector3 {
+        x: x / ACCELEROMETER_RAW_PER_MG,
+        y: y / ACCELEROMETER_RAW_PER_MG,
+        z: z / ACCELEROMETER_RAW_PER_MG,
+    })
+}
+
+fn decode_rotation(data: &[u8]) -> Result<Vector3> {
+    let [x, y, z] = read_axes(data)?;
+    Ok(Vector3 {
+        x: x * GYRO_DPS_PER_RAW,
+        y: y * GYRO_DPS_PER_RAW,
+        z: z * GYRO_DPS_PER_RAW,
+    })
+}
+
+// Degrees, reported in reverse order and with z negated
+fn decode_tilt(data: &[u8]) -> Result<Vector3> {
+    let [z, y, x] = read_axes(data)?;
+    Ok(Vector3 { x, y, z: -z })
+}
+
+
+// mG
+pub struct Accelerometer {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+#[async_trait]
+impl ThreeAxisSensorType for Accelerometer {
+    async fn read(&self) -> Result<Vector3> {
+        read_decoded(&self.hub, self.port_id, AccelerometerModes::Grv as u8, decode_acceleration).await
+    }
+
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<Vector3>>> {
+        decoded_stream(&self.hub, self.port_id, AccelerometerModes::Grv as u8, delta, decode_acceleration).await
+    }
+}
+
+
+// Degrees per second
+pub struct Gyro {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+#[async_trait]
+impl ThreeAxisSensorType for Gyro {
+    async fn read(&self) -> Result<Vector3> {
+        read_decoded(&self.hub, self.port_id, GyroModes::Rot as u8, decode_rotation).await
+    }
+
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<Vector3>>> {
+        decoded_stream(&self.hub, self.port_id, GyroModes::Rot as u8, delta, decode_rotation).await
+    }
+}
+
+
+// Degrees. x is the roll, y the pitch and z the heading.
+pub struct TiltSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl TiltSensor {
+    // true once the roll or the pitch go beyond max_angle, false once both are back within it.
+    // Only changes are sent.
+    pub async fn tip_over_stream(&self, max_angle: f32) -> Result<NotificationStream<Timestamped<bool>>> {
+        let mut tipped_over = None;
+        let tilts = self.stream(1).await?;
+        Ok(Box::pin(tilts.filter_map(move |tilt| {
+            let now = tilt.value.x.abs() > max_angle || tilt.value.y.abs() > max_angle;
+            if tipped_over == Some(now) {
+                return None;
+            }
+            tipped_over = Some(now);
+            Some(Timestamped { timestamp: tilt.timestamp, value: now })
+        })))
+    }
+}
+
+#[async_trait]
+impl ThreeAxisSensorType for TiltSensor {
+    async fn read(&self) -> Result<Vector3> {
+        read_decoded(&self.hub, self.port_id, TiltModes::Pos as u8, decode_tilt).await
+    }
+
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<Vector3>>> {
+        decoded_stream(&self.hub, self.port_id, TiltModes::Pos as u8, delta, decode_tilt).await
+    }
+}
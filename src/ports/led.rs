@@ -0,0 +1,98 @@
+// The hub's status LED (PortType::HubLed)
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::{
+    hub::Hub,
+    lego::{
+        message_parameters::{
+            WriteDirectModeDataCommands,
+            SetRgbColorNoPayload,
+            SetRgbColorPayload,
+        },
+        consts::{
+            Color,
+            HubLedModes,
+        },
+    }, LedType
+};
+use super::write_direct;
+
+// Time between two colors of a fade
+const FADE_STEP: Duration = Duration::from_millis(50);
+
+
+#[derive(Clone)]
+pub struct Led {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl Led {
+    async fn write(&self, mode: HubLedModes, payload: WriteDirectModeDataCommands) -> Result<()> {
+        write_direct(&self.hub, self.port_id, mode as u8, payload).await
+    }
+}
+
+#[async_trait]
+impl LedType for Led {
+
+    async fn set_color(&self, color: Color) -> Result<()> {
+        self.write(
+            HubLedModes::Color,
+            WriteDirectModeDataCommands::SetRgbColorNo(
+                SetRgbColorNoPayload {
+                    color,
+                }
+            )
+        ).await
+    }
+
+    async fn set_rgb(&self, red: u8, green: u8, blue: u8) -> Result<()> {
+        self.write(
+            HubLedModes::Rgb,
+            WriteDirectModeDataCommands::SetRgbColor(
+                SetRgbColorPayload {
+                    red,
+                    green,
+                    blue,
+                }
+            )
+        ).await
+    }
+
+    fn blink(&self, color: Color, period: Duration, count: Option<u32>) -> JoinHandle<Result<()>> {
+        let led = self.clone();
+        tokio::spawn(async move {
+            let mut blinks = 0;
+            while count.is_none_or(|count| blinks < count) {
+                led.set_color(color).await?;
+                time::sleep(period / 2).await;
+                led.set_color(Color::Black).await?;
+                time::sleep(period / 2).await;
+                blinks += 1;
+            }
+            Ok(())
+        })
+    }
+
+    fn fade(&self, from: (u8, u8, u8), to: (u8, u8, u8), duration: Duration) -> JoinHandle<Result<()>> {
+        let led = self.clone();
+        tokio::spawn(async move {
+            let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+            let mix = |a: u8, b: u8, step: u32| -> u8 {
+                (a as i32 + (b as i32 - a as i32) * step as i32 / steps as i32) as u8
+            };
+            led.set_rgb(from.0, from.1, from.2).await?;
+            for step in 1..=steps {
+                time::sleep(duration / steps).await;
+                led.set_rgb(mix(from.0, to.0, step), mix(from.1, to.1, step), mix(from.2, to.2, step)).await?;
+            }
+            Ok(())
+        })
+    }
+}
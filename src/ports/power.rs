@@ -0,0 +1,192 @@
+// The hub's own voltage and current sensors, and a monitor built on top of them
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    hub::Hub,
+    lego::{
+        NotificationStream,
+        consts::{
+            CurrentSensorModes,
+            VoltageSensorModes,
+        },
+    }, ScalarSensorType
+};
+use super::{
+    Timestamped,
+    read_si,
+    si_stream,
+};
+
+// How often a sustained overcurrent is checked for, when no new readings arrive
+const OVERCURRENT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+
+// mV
+pub struct VoltageSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+#[async_trait]
+impl ScalarSensorType for VoltageSensor {
+    async fn read(&self) -> Result<f32> {
+        read_si(&self.hub, self.port_id, VoltageSensorModes::VltL as u8).await
+    }
+
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<f32>>> {
+        si_stream(&self.hub, self.port_id, VoltageSensorModes::VltL as u8, delta).await
+    }
+}
+
+
+// mA
+pub struct CurrentSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+#[async_trait]
+impl ScalarSensorType for CurrentSensor {
+    async fn read(&self) -> Result<f32> {
+        read_si(&self.hub, self.port_id, CurrentSensorModes::CurL as u8).await
+    }
+
+    async fn stream(&self, delta: u32) -> Result<NotificationStream<Timestamped<f32>>> {
+        si_stream(&self.hub, self.port_id, CurrentSensorModes::CurL as u8, delta).await
+    }
+}
+
+
+
+/***************************************/
+/*********** BatteryMonitor ************/
+/***************************************/
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryThresholds {
+    pub low_voltage:            f32,        // mV
+    pub overcurrent:            f32,        // mA
+    pub overcurrent_duration:   Duration,   // How long the current has to stay above overcurrent
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatteryEvent {
+    LowVoltage(f32),        // mV, sent once the voltage drops below the threshold
+    VoltageRecovered(f32),  // mV
+    Overcurrent(f32),       // mA, sent once the current stays above the threshold long enough
+    CurrentRecovered(f32),  // mA
+}
+
+// Watches the voltage and current of a hub.
+// Stop the motors on LowVoltage / Overcurrent, before the hub browns out.
+pub struct BatteryMonitor {
+    pub voltage:        VoltageSensor,
+    pub current:        CurrentSensor,
+    pub thresholds:     BatteryThresholds,
+}
+
+impl BatteryMonitor {
+    pub fn new(voltage: VoltageSensor, current: CurrentSensor, thresholds: BatteryThresholds) -> Self {
+        Self {
+            voltage,
+            current,
+            thresholds,
+        }
+    }
+
+    // The monitoring runs in the background until the stream is dropped or the hub stops sending values
+    pub async fn events(&self) -> Result<NotificationStream<Timestamped<BatteryEvent>>> {
+        let mut voltages = self.voltage.stream(1).await?;
+        let mut currents = self.current.stream(1).await?;
+        let mut watcher = BatteryWatcher::new(self.thresholds);
+        let (tx, rx) = mpsc::channel(16);
+
+        tokio::spawn(async move {
+            let mut ticks = time::interval(OVERCURRENT_CHECK_INTERVAL);
+            loop {
+                let event = tokio::select! {
+                    voltage = voltages.next() => match voltage {
+                        Some(voltage) => watcher.on_voltage(voltage.value),
+                        None => break,
+                    },
+                    current = currents.next() => match current {
+                        Some(current) => watcher.on_current(current.value),
+                        None => break,
+                    },
+                    _ = ticks.tick() => watcher.check_overcurrent(),
+                    _ = tx.closed() => break,
+                };
+                if let Some(event) = event {
+                    if tx.send(Timestamped::now(event)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Box::pin(ReceiverStream::new(rx)))
+    }
+}
+
+struct BatteryWatcher {
+    thresholds:         BatteryThresholds,
+    low_voltage:        bool,
+    overcurrent:        bool,
+    overcurrent_since:  Option<Instant>,
+    last_current:       f32,
+}
+
+impl BatteryWatcher {
+    fn new(thresholds: BatteryThresholds) -> Self {
+        Self {
+            thresholds,
+            low_voltage:        false,
+            overcurrent:        false,
+            overcurrent_since:  None,
+            last_current:       0.0,
+        }
+    }
+
+    fn on_voltage(&mut self, voltage: f32) -> Option<BatteryEvent> {
+        let low_voltage = voltage < self.thresholds.low_voltage;
+        if low_voltage == self.low_voltage {
+            return None;
+        }
+        self.low_voltage = low_voltage;
+        match low_voltage {
+            true => Some(BatteryEvent::LowVoltage(voltage)),
+            false => Some(BatteryEvent::VoltageRecovered(voltage)),
+        }
+    }
+
+    fn on_current(&mut self, current: f32) -> Option<BatteryEvent> {
+        self.last_current = current;
+        if current > self.thresholds.overcurrent {
+            self.overcurrent_since.get_or_insert_with(Instant::now);
+            return self.check_overcurrent();
+        }
+        self.overcurrent_since = None;
+        if self.overcurrent {
+            self.overcurrent = false;
+            return Some(BatteryEvent::CurrentRecovered(current));
+        }
+        None
+    }
+
+    fn check_overcurrent(&mut self) -> Option<BatteryEvent> {
+        match self.overcurrent_since {
+            Some(since) if !self.overcurrent && since.elapsed() >= self.thresholds.overcurrent_duration => {
+                self.overcurrent = true;
+                Some(BatteryEvent::Overcurrent(self.last_current))
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// SPIKE Prime sensors: color (45605), distance (45604) and force (45606)
+use anyhow::Result;
+use tokio_stream::StreamExt;
+
+use crate::{
+    hub::Hub,
+    lego::{
+        NotificationStream,
+        upstream_messages::FrameReader,
+        message_parameters::{
+            WriteDirectModeDataCommands,
+            SetBrightnessPayload,
+        },
+        consts::{
+            Color,
+            TechnicColorSensorModes,
+            TechnicDistanceSensorModes,
+            TechnicForceSensorModes,
+        },
+    },
+};
+use super::{
+    Timestamped,
+    decoded_stream,
+    read_decoded,
+    write_direct,
+};
+use super::color_distance::{
+    decode_color,
+    decode_u8,
+};
+
+
+/***************************************/
+/************ Color Sensor *************/
+/***************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hsv {
+    pub hue:        u16,    // Degrees, 0-359
+    pub saturation: u16,    // %
+    pub value:      u16,    // %
+}
+
+fn decode_hsv(data: &[u8]) -> Result<Hsv> {
+    let mut reader = FrameReader::new(data);
+    Ok(Hsv {
+        hue:        reader.u16("hue")?,
+        saturation: reader.u16("saturation")?,
+        value:      reader.u16("value")?,
+    })
+}
+
+// Unlike the Color & Distance sensor, the SPIKE Prime one reports the overall intensity as well
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawRgbI {
+    pub red:        u16,
+    pub green:      u16,
+    pub blue:       u16,
+    pub intensity:  u16,
+}
+
+fn decode_rgbi(data: &[u8]) -> Result<RawRgbI> {
+    let mut reader = FrameReader::new(data);
+    Ok(RawRgbI {
+        red:        reader.u16("red")?,
+        green:      reader.u16("green")?,
+        blue:       reader.u16("blue")?,
+        intensity:  reader.u16("intensity")?,
+    })
+}
+
+// Reading or streaming one mode switches the port to it, and ends the streams of the other modes
+pub struct TechnicColorSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl TechnicColorSensor {
+    // Color::None when nothing is in front of the sensor
+    pub async fn color(&self) -> Result<Color> {
+        read_decoded(&self.hub, self.port_id, TechnicColorSensorModes::Color as u8, decode_color).await
+    }
+
+    pub async fn color_stream(&self) -> Result<NotificationStream<Timestamped<Color>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicColorSensorModes::Color as u8, 1, decode_color).await
+    }
+
+    // %
+    pub async fn reflected_light(&self) -> Result<u8> {
+        read_decoded(&self.hub, self.port_id, TechnicColorSensorModes::Reflt as u8, decode_u8).await
+    }
+
+    pub async fn reflected_light_stream(&self) -> Result<NotificationStream<Timestamped<u8>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicColorSensorModes::Reflt as u8, 1, decode_u8).await
+    }
+
+    // %
+    pub async fn ambient_light(&self) -> Result<u8> {
+        read_decoded(&self.hub, self.port_id, TechnicColorSensorModes::Ambi as u8, decode_u8).await
+    }
+
+    pub async fn ambient_light_stream(&self) -> Result<NotificationStream<Timestamped<u8>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicColorSensorModes::Ambi as u8, 1, decode_u8).await
+    }
+
+    pub async fn rgb(&self) -> Result<RawRgbI> {
+        read_decoded(&self.hub, self.port_id, TechnicColorSensorModes::RgbI as u8, decode_rgbi).await
+    }
+
+    pub async fn rgb_stream(&self) -> Result<NotificationStream<Timestamped<RawRgbI>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicColorSensorModes::RgbI as u8, 1, decode_rgbi).await
+    }
+
+    pub async fn hsv(&self) -> Result<Hsv> {
+        read_decoded(&self.hub, self.port_id, TechnicColorSensorModes::Hsv as u8, decode_hsv).await
+    }
+
+    pub async fn hsv_stream(&self) -> Result<NotificationStream<Timestamped<Hsv>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicColorSensorModes::Hsv as u8, 1, decode_hsv).await
+    }
+
+    // Brightness (0-100%) of each of the 3 LEDs around the lens
+    pub async fn set_lights(&self, brightness: [u8; 3]) -> Result<()> {
+        write_direct(
+            &self.hub,
+            self.port_id,
+            TechnicColorSensorModes::Light as u8,
+            WriteDirectModeDataCommands::SetBrightness(
+                SetBrightnessPayload {
+                    brightness: brightness.to_vec(),
+                }
+            ),
+        ).await
+    }
+}
+
+
+
+/***************************************/
+/*********** Distance Sensor ***********/
+/***************************************/
+
+// None when nothing is in range
+fn decode_distance(data: &[u8]) -> Result<Option<u16>> {
+    let distance = FrameReader::new(data).i16("distance")?;
+    Ok(u16::try_from(distance).ok())
+}
+
+pub struct TechnicDistanceSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl TechnicDistanceSensor {
+    // mm
+    pub async fn distance(&self) -> Result<Option<u16>> {
+        read_decoded(&self.hub, self.port_id, TechnicDistanceSensorModes::Distl as u8, decode_distance).await
+    }
+
+    pub async fn distance_stream(&self) -> Result<NotificationStream<Timestamped<Option<u16>>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicDistanceSensorModes::Distl as u8, 1, decode_distance).await
+    }
+
+    // Faster updates, shorter range
+    pub async fn short_distance_stream(&self) -> Result<NotificationStream<Timestamped<Option<u16>>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicDistanceSensorModes::Dists as u8, 1, decode_distance).await
+    }
+
+    // Brightness (0-100%) of the four segments around the "eyes"
+    pub async fn set_lights(&self, top_left: u8, top_right: u8, bottom_left: u8, bottom_right: u8) -> Result<()> {
+        write_direct(
+            &self.hub,
+            self.port_id,
+            TechnicDistanceSensorModes::Light as u8,
+            WriteDirectModeDataCommands::SetBrightness(
+                SetBrightnessPayload {
+                    brightness: vec![top_left, top_right, bottom_left, bottom_right],
+                }
+            ),
+        ).await
+    }
+}
+
+
+
+/***************************************/
+/************ Force Sensor *************/
+/***************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForceSensorEvent {
+    Touched,    // Any force
+    Pressed,    // At least the pressing force
+    Released,   // No force at all
+}
+
+// Newtons
+fn decode_force(data: &[u8]) -> Result<f32> {
+    Ok(FrameReader::new(data).u8("force")? as f32 / 10.0)
+}
+
+fn decode_touched(data: &[u8]) -> Result<bool> {
+    Ok(FrameReader::new(data).u8("touched")? != 0)
+}
+
+pub struct TechnicForceSensor {
+    pub hub:        Hub,
+    pub port_id:    u8,
+}
+
+impl TechnicForceSensor {
+    // Newtons, up to 10
+    pub async fn force(&self) -> Result<f32> {
+        read_decoded(&self.hub, self.port_id, TechnicForceSensorModes::Force as u8, decode_force).await
+    }
+
+    pub async fn force_stream(&self) -> Result<NotificationStream<Timestamped<f32>>> {
+        decoded_stream(&self.hub, self.port_id, TechnicForceSensorModes::Force as u8, 1, decode_force).await
+    }
+
+    pub async fn is_touched(&self) -> Result<bool> {
+        read_decoded(&self.hub, self.port_id, TechnicForceSensorModes::Touched as u8, decode_touched).await
+    }
+
+    // Only changes are sent, starting with the current state
+    pub async fn events(&self, pressing_force: f32) -> Result<NotificationStream<Timestamped<ForceSensorEvent>>> {
+        let mut last = None;
+        let forces = self.force_stream().await?;
+        Ok(Box::pin(forces.filter_map(move |force| {
+            let event = if force.value >= pressing_force {
+                ForceSensorEvent::Pressed
+            } else if force.value > 0.0 {
+                ForceSensorEvent::Touched
+            } else {
+                ForceSensorEvent::Released
+            };
+            if last == Some(event) {
+                return None;
+            }
+            last = Some(event);
+            Some(Timestamped { timestamp: force.timestamp, value: event })
+        })))
+    }
+}
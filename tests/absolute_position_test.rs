@@ -0,0 +1,52 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached;
+
+    #[tokio::test]
+    async fn reset_to_absolute_zero_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // Technic medium angular motor
+        peer.send(attached(0x00, 0x30)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // APOS mode, then the value: -90 degrees
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x03, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x03, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x00]);
+            peer.send(vec![0x06, 0x00, 0x45, 0x00, 0xa6, 0xff]).unwrap();
+            // [len, hub, PortOutputCommand, port, startup, WriteDirectModeData, mode, position]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0b, 0x00, 0x81, 0x00, 0x10, 0x51, 0x02, 0xa6, 0xff, 0xff, 0xff]);
+            peer
+        });
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        _ = motor.reset_to_absolute_zero(StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn no_absolute_encoder_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // Train motor
+        peer.send(attached(0x00, 0x02)).unwrap();
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        assert!(motor.absolute_position().await.is_err());
+    }
+}
@@ -0,0 +1,53 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            attached_io::AttachmentEvent,
+            consts::PortType,
+        },
+        HubType,
+    };
+
+    use crate::common::{attached, attached_motor};
+
+    #[tokio::test]
+    async fn port_table_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reply_timeout(Duration::from_millis(50));
+        let mut events = hub.get_attachment_stream().await.unwrap();
+
+        peer.send(attached_motor(0x00)).unwrap();
+        // Hub LED (not a motor) on port 50
+        peer.send(attached(0x32, 0x17)).unwrap();
+        // Port 0 detached
+        peer.send(vec![0x05, 0x00, 0x04, 0x00, 0x00]).unwrap();
+
+        assert_eq!(events.next().await.unwrap().port_id(), 0x00);
+        assert_eq!(events.next().await.unwrap().port_id(), 0x32);
+        match events.next().await.unwrap() {
+            AttachmentEvent::Detached { port_id, previous } => {
+                assert_eq!(port_id, 0x00);
+                assert_eq!(previous.unwrap().io_type, Some(PortType::TechnicLargeLinearMotor));
+            },
+            _ => panic!("Expected a detach event"),
+        }
+
+        let devices = hub.get_attached_devices().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[&0x32].io_type, Some(PortType::HubLed));
+
+        // Neither the LED nor an empty port is a motor
+        assert!(hub.get_motor(0x32).await.is_err());
+        assert!(hub.get_motor(0x00).await.is_err());
+    }
+}
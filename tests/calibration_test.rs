@@ -0,0 +1,121 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryPeer,
+            InMemoryTransport,
+        },
+        calibration::{
+            CalibrationSettings,
+            SteeringCalibration,
+            calibrate_steering,
+        },
+        HubType,
+    };
+
+    use tokio::task::JoinHandle;
+
+    use crate::common::attached;
+
+    fn position_value(position: i32) -> Vec<u8> {
+        let mut frame = vec![0x08, 0x00, 0x45, 0x00];
+        frame.extend(position.to_le_bytes());
+        frame
+    }
+
+    // Plays a motor between end stops at -50 and 100 degrees, and returns the presets of its position
+    fn steering_motor(mut peer: InMemoryPeer, absolute: bool) -> JoinHandle<Vec<i32>> {
+        tokio::spawn(async move {
+            let mut position: i32 = 0;
+            let mut presets = Vec::new();
+            while let Some(frame) = peer.recv().await {
+                match (frame[2], frame.get(5).copied(), frame.get(6)) {
+                    // Input format setup: acknowledged, and the current position once notifications are on
+                    (0x41, _, _) => {
+                        let mut reply = frame.clone();
+                        reply[2] = 0x47;
+                        peer.send(reply).unwrap();
+                        if frame[4] == 0x02 && frame[9] == 0x01 {
+                            peer.send(position_value(position)).unwrap();
+                        }
+                    },
+                    // Start speed: moves until the end stop, then stalls
+                    (0x81, Some(0x07), Some(speed)) => {
+                        let (end_stop, step) = if (*speed as i8) > 0 { (100, 10) } else { (-50, -10) };
+                        while position != end_stop {
+                            position += step;
+                            peer.send(position_value(position)).unwrap();
+                        }
+                    },
+                    // Preset of the position
+                    (0x81, Some(0x51), Some(0x02)) => {
+                        presets.push(i32::from_le_bytes(frame[7..11].try_into().unwrap()));
+                    },
+                    // Going to the center
+                    (0x81, Some(0x0d), _) => {
+                        peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x01]).unwrap();
+                        peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x0a]).unwrap();
+                        if !absolute {
+                            break;
+                        }
+                    },
+                    // The absolute position, at the center
+                    (0x21, _, _) => {
+                        peer.send(vec![0x06, 0x00, 0x45, 0x00, 0x2a, 0x00]).unwrap();
+                        break;
+                    },
+                    _ => {},
+                }
+            }
+            presets
+        })
+    }
+
+    #[tokio::test]
+    async fn calibrate_steering_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // Technic medium angular motor
+        peer.send(attached(0x00, 0x30)).unwrap();
+        let fake_hub = steering_motor(peer, true);
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        let settings = CalibrationSettings {
+            stall_time: Duration::from_millis(50),
+            ..CalibrationSettings::default()
+        };
+        let calibration = calibrate_steering(&motor, &settings).await.unwrap();
+
+        assert_eq!(calibration, SteeringCalibration { min: -75, max: 75, center: Some(42) });
+        assert_eq!(calibration.clamp(100), 75);
+        // The motor was at the negative end stop
+        assert_eq!(fake_hub.await.unwrap(), vec![-75]);
+    }
+
+    #[tokio::test]
+    async fn calibrate_steering_without_absolute_encoder_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // Train motor - no absolute position to ask for
+        peer.send(attached(0x00, 0x02)).unwrap();
+        let fake_hub = steering_motor(peer, false);
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        let settings = CalibrationSettings {
+            stall_time: Duration::from_millis(50),
+            passes:     1,
+            ..CalibrationSettings::default()
+        };
+        let calibration = calibrate_steering(&motor, &settings).await.unwrap();
+
+        assert_eq!(calibration, SteeringCalibration { min: -75, max: 75, center: None });
+        assert_eq!(fake_hub.await.unwrap(), vec![-75]);
+    }
+}
@@ -0,0 +1,53 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            consts::Color,
+        },
+        HubType,
+    };
+
+    use crate::common::attached;
+
+    #[tokio::test]
+    async fn color_distance_sensor_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // HubAttachedIO of a Color & Distance sensor
+        peer.send(attached(0x01, 0x25)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // Color mode, then the value
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x01, 0x00]);
+            peer.send(vec![0x05, 0x00, 0x45, 0x01, 0x09]).unwrap();
+            // Notifications of the color mode
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x45, 0x01, 0xff]).unwrap();
+            // LED color output mode, then the color
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x01, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x01, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, 0x01, 0x10, 0x51, 0x05, 0x06]);
+            peer
+        });
+
+        let sensor = hub.get_color_distance_sensor(0x01).await.unwrap();
+        assert_eq!(sensor.color().await.unwrap(), Color::Red);
+
+        let mut colors = sensor.color_stream().await.unwrap();
+        assert_eq!(colors.next().await.unwrap().value, Color::None);
+
+        sensor.set_led_color(Color::Green).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+extern crate rust_powered_lego;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            port_modes::{
+                CombinedModeSetup,
+                DatasetValue,
+                ModeSet,
+            },
+        },
+        HubType,
+    };
+
+    #[test]
+    fn mode_set_test() {
+        let modes = ModeSet(0x000e);
+        assert_eq!(modes.modes(), vec![1, 2, 3]);
+        assert!(modes.includes(ModeSet::from_modes(&[1, 3]).unwrap()));
+        assert!(!modes.includes(ModeSet::from_modes(&[0, 1]).unwrap()));
+        // Only 16 modes fit
+        assert!(ModeSet::from_modes(&[15]).is_ok());
+        assert!(ModeSet::from_modes(&[16]).is_err());
+    }
+
+    #[tokio::test]
+    async fn combined_mode_setup_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // PortInformationRequest of the possible mode combinations
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x02]);
+            // Combination 0 is modes 1, 2 and 3
+            peer.send(vec![0x09, 0x00, 0x43, 0x00, 0x02, 0x0e, 0x00, 0x00, 0x00]).unwrap();
+            // VALUE FORMAT of mode 1 (speed) and mode 2 (position)
+            assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x22, 0x00, 0x01, 0x80]);
+            peer.send(vec![0x0a, 0x00, 0x44, 0x00, 0x01, 0x80, 0x01, 0x00, 0x04, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x22, 0x00, 0x02, 0x80]);
+            peer.send(vec![0x0a, 0x00, 0x44, 0x00, 0x02, 0x80, 0x01, 0x02, 0x04, 0x00]).unwrap();
+            // Lock
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x02]);
+            // Each mode is set up on its own
+            for mode in [0x01, 0x02] {
+                let setup = vec![0x0a, 0x00, 0x41, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01];
+                assert_eq!(peer.recv().await.unwrap(), setup);
+                peer.send(vec![0x0a, 0x00, 0x47, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            }
+            // Combination 0: mode 1 dataset 0, mode 2 dataset 0
+            assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x42, 0x00, 0x01, 0x00, 0x10, 0x20]);
+            // Unlock, with multi-update enabled
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x03]);
+            peer.send(vec![0x07, 0x00, 0x48, 0x00, 0x80, 0x03, 0x00]).unwrap();
+            peer
+        });
+
+        let setup = CombinedModeSetup::new(0x00)
+            .mode(0x01, 1)
+            .mode(0x02, 1);
+        let decoder = hub.setup_port_combined_mode(setup).await.unwrap();
+        let peer = fake_hub.await.unwrap();
+
+        let mut values = hub.get_combined_value_stream(0x00).await.unwrap();
+        // Speed -10, position 360
+        peer.send(vec![0x0b, 0x00, 0x46, 0x00, 0x03, 0x00, 0xf6, 0x68, 0x01, 0x00, 0x00]).unwrap();
+        let decoded = decoder.decode(&values.next().await.unwrap()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!((decoded[0].mode, decoded[0].value), (0x01, DatasetValue::Int(-10)));
+        assert_eq!((decoded[1].mode, decoded[1].value), (0x02, DatasetValue::Int(360)));
+
+        // Only the position
+        peer.send(vec![0x0a, 0x00, 0x46, 0x00, 0x02, 0x00, 0x69, 0x01, 0x00, 0x00]).unwrap();
+        let decoded = decoder.decode(&values.next().await.unwrap()).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!((decoded[0].mode, decoded[0].value), (0x02, DatasetValue::Int(361)));
+    }
+
+    #[tokio::test]
+    async fn failed_setup_unlocks_port_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x09, 0x00, 0x43, 0x00, 0x02, 0x0e, 0x00, 0x00, 0x00]).unwrap();
+            _ = peer.recv().await;
+            peer.send(vec![0x0a, 0x00, 0x44, 0x00, 0x01, 0x80, 0x01, 0x00, 0x04, 0x00]).unwrap();
+            // Locked, then the mode setup is rejected
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x02]);
+            _ = peer.recv().await;
+            peer.send(vec![0x05, 0x00, 0x05, 0x41, 0x06]).unwrap();
+            // Unlocked without updates
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x04]);
+            peer
+        });
+
+        let setup = CombinedModeSetup::new(0x00).mode(0x01, 1);
+        assert!(hub.setup_port_combined_mode(setup).await.is_err());
+        let mut peer = fake_hub.await.unwrap();
+
+        // Out of range modes are rejected before anything is locked
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x09, 0x00, 0x43, 0x00, 0x02, 0x0e, 0x00, 0x00, 0x00]).unwrap();
+            peer
+        });
+        let setup = CombinedModeSetup::new(0x00).mode(0x10, 1);
+        assert!(hub.setup_port_combined_mode(setup).await.is_err());
+        let mut peer = fake_hub.await.unwrap();
+        assert_eq!(peer.try_recv(), None);
+    }
+}
@@ -0,0 +1,127 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            CommandOutcome,
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+            consts::{
+                EndState,
+                Profile,
+            },
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn command_feedback_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            // In progress (and the previous command discarded), then another port completes, then ours
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x05]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x01, 0x0a]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x0a]).unwrap();
+            _ = peer.recv().await;
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x0c]).unwrap();
+            peer
+        });
+
+        let handle = motor.start_speed_for_deg(
+            90, 50, 100, EndState::HOLD, Profile::AccDec, StartupAndCompletionInfo::BufferAndFeedback).await.unwrap();
+        assert_eq!(handle.wait().await.unwrap(), CommandOutcome::Completed);
+
+        let handle = motor.start_power(10, StartupAndCompletionInfo::BufferAndFeedback).await.unwrap();
+        assert_eq!(handle.wait().await.unwrap(), CommandOutcome::Discarded);
+        let _peer = fake_hub.await.unwrap();
+
+        // No feedback asked - nothing to wait for
+        let handle = motor.start_power(0, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.unwrap();
+        assert!(handle.wait().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn stale_feedback_is_ignored_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+
+        // The first command is still running when the second one is sent
+        let _first = motor.start_power(10, StartupAndCompletionInfo::BufferAndFeedback).await.unwrap();
+        let second = motor.start_power(20, StartupAndCompletionInfo::BufferAndFeedback).await.unwrap();
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            _ = peer.recv().await;
+            // The first command went idle, then an output command of another port is rejected
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x08]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x01]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x05, 0x81, 0x06]).unwrap();
+            // Now the second one
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x0a]).unwrap();
+            peer
+        });
+        assert_eq!(second.wait().await.unwrap(), CommandOutcome::Completed);
+        let mut peer = fake_hub.await.unwrap();
+
+        // Rejected before it was accepted
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x05, 0x00, 0x05, 0x81, 0x06]).unwrap();
+        });
+        let handle = motor.start_power(10, StartupAndCompletionInfo::BufferAndFeedback).await.unwrap();
+        assert!(handle.wait().await.is_err());
+        fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn immediate_completion_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+
+        // Nothing else pending on the port, so a lone "completed" is about this command
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x0a]).unwrap();
+            peer
+        });
+        let handle = motor.set_abs_position(0, StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback).await.unwrap();
+        assert_eq!(handle.wait().await.unwrap(), CommandOutcome::Completed);
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn feedback_timeout_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reply_timeout(Duration::from_millis(50));
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+
+        // No feedback at all
+        let handle = motor.start_power(10, StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback).await.unwrap();
+        assert!(handle.wait().await.is_err());
+        _ = peer.recv().await;
+
+        // Taken up, but never finished
+        let handle = motor.start_power(10, StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback).await.unwrap();
+        peer.send(vec![0x05, 0x00, 0x82, 0x00, 0x01]).unwrap();
+        assert!(handle.wait_for(Duration::from_millis(100)).await.is_err());
+    }
+}
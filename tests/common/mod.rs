@@ -0,0 +1,25 @@
+// Frames shared by the tests.
+// Not every test uses all of them.
+#![allow(dead_code)]
+
+// HubAttachedIO of a device of the given io type id
+pub fn attached(port_id: u8, io_type: u8) -> Vec<u8> {
+    vec![0x0f, 0x00, 0x04, port_id, 0x01, io_type, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x10]
+}
+
+// HubAttachedIO of a Technic large motor
+pub fn attached_motor(port_id: u8) -> Vec<u8> {
+    attached(port_id, 0x2e)
+}
+
+// HubAttachedIO of a virtual port of two Technic large motors
+pub fn attached_virtual(port_id: u8, port_a: u8, port_b: u8) -> Vec<u8> {
+    vec![0x09, 0x00, 0x04, port_id, 0x02, 0x2e, 0x00, port_a, port_b]
+}
+
+// The payload of a range in PortModeInformation replies
+pub fn range(min: f32, max: f32) -> Vec<u8> {
+    let mut payload = Vec::from(min.to_le_bytes());
+    payload.extend_from_slice(&max.to_le_bytes());
+    payload
+}
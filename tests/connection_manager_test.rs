@@ -0,0 +1,52 @@
+extern crate rust_powered_lego;
+
+#[cfg(test)]
+mod tests {
+    use btleplug::api::PeripheralProperties;
+    use uuid::Uuid;
+
+    use rust_powered_lego::{
+        connection_manager::ManufacturerData,
+        lego::consts::{
+            HubKind,
+            LEGO_HUB_SERVICE_UUID,
+            LEGO_MANUFACTURER_ID,
+        },
+    };
+
+    // The battery service - anything but the hub service
+    const OTHER_SERVICE_UUID: Uuid = Uuid::from_u128(0x0000180f_0000_1000_8000_00805f9b34fb);
+
+    fn advertisement(services: Vec<Uuid>, manufacturer_id: u16, data: &[u8]) -> PeripheralProperties {
+        let mut properties = PeripheralProperties {
+            local_name: Some("Technic Hub".to_string()),
+            services,
+            ..PeripheralProperties::default()
+        };
+        properties.manufacturer_data.insert(manufacturer_id, data.to_vec());
+        properties
+    }
+
+    #[test]
+    fn lego_hub_advertisement_test() {
+        // A Technic hub, button pressed
+        let properties = advertisement(vec![LEGO_HUB_SERVICE_UUID], LEGO_MANUFACTURER_ID, &[0x01, 0x80, 0x06, 0x00, 0x41, 0x00]);
+        let data = ManufacturerData::from_advertisement(&properties).unwrap();
+        assert!(data.button_pressed);
+        assert_eq!(data.kind, Some(HubKind::TechnicHub));
+    }
+
+    #[test]
+    fn other_advertisements_test() {
+        let hub_data = [0x00, 0x80, 0x06, 0x00, 0x41, 0x00];
+        // Without the hub service
+        let properties = advertisement(vec![OTHER_SERVICE_UUID], LEGO_MANUFACTURER_ID, &hub_data);
+        assert!(ManufacturerData::from_advertisement(&properties).is_none());
+        // Someone else's manufacturer data
+        let properties = advertisement(vec![LEGO_HUB_SERVICE_UUID], 0x004c, &hub_data);
+        assert!(ManufacturerData::from_advertisement(&properties).is_none());
+        // Too short to be a hub's
+        let properties = advertisement(vec![LEGO_HUB_SERVICE_UUID], LEGO_MANUFACTURER_ID, &[0x00, 0x80]);
+        assert!(ManufacturerData::from_advertisement(&properties).is_none());
+    }
+}
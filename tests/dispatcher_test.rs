@@ -0,0 +1,27 @@
+extern crate rust_powered_lego;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::InMemoryTransport,
+        HubType,
+    };
+
+    #[tokio::test]
+    async fn port_value_stream_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        let mut values = hub.get_port_value_stream(0x01).await.unwrap();
+
+        // A value of another port first - it shouldn't show up
+        peer.send(vec![0x05, 0x00, 0x45, 0x00, 0x10]).unwrap();
+        peer.send(vec![0x05, 0x00, 0x45, 0x01, 0x20]).unwrap();
+
+        let value = values.next().await.unwrap();
+        assert_eq!(value.port_id, 0x01);
+        assert_eq!(value.data, vec![0x20]);
+    }
+}
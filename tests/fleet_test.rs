@@ -0,0 +1,52 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        fleet::{
+            Fleet,
+            HubId,
+        },
+        hub::Hub,
+        lego::InMemoryTransport,
+        HubType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn stop_all_test() {
+        let mut fleet = Fleet::new();
+        let mut peers = Vec::new();
+        for (name, ports) in [("Front", vec![0x00, 0x01]), ("Rear", vec![0x02])] {
+            let (transport, peer) = InMemoryTransport::new();
+            let hub = Hub::with_transport(transport).await.unwrap();
+            for port_id in ports.iter() {
+                peer.send(attached_motor(*port_id)).unwrap();
+                // Waits for the hub to see it
+                _ = hub.get_attached_device(*port_id).await.unwrap();
+            }
+            fleet.insert(HubId::Name(name.to_string()), hub);
+            peers.push((peer, ports));
+        }
+        assert!(fleet.hub(&HubId::Name("Rear".to_string())).is_some());
+        fleet.stop_all().await.unwrap();
+
+        for (mut peer, ports) in peers {
+            for port_id in ports {
+                // Braking: StartPower(127)
+                assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, port_id, 0x10, 0x51, 0x00, 0x7f]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_unknown_hub_test() {
+        let mut fleet = Fleet::new();
+        let err = fleet.connect(&[HubId::Name("Front".to_string())]).await.unwrap_err();
+        assert!(err.to_string().contains("Front: not found by the scan"));
+        assert!(fleet.hubs().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        connection_manager::ManufacturerData,
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            consts::{
+                HubKind,
+                TechnicHubPorts,
+            },
+        },
+        HubType,
+    };
+
+    use crate::common::attached;
+
+    #[test]
+    fn parse_manufacturer_data_test() {
+        // A Technic hub, button pressed
+        let data = ManufacturerData::parse(&[0x01, 0x80, 0x06, 0x00, 0x41, 0x00]).unwrap();
+        assert!(data.button_pressed);
+        assert_eq!(data.kind, Some(HubKind::TechnicHub));
+        assert_eq!(data.capabilities, 0x06);
+        assert_eq!(data.status, 0x41);
+
+        assert_eq!(ManufacturerData::parse(&[0x00, 0x41, 0x02, 0x00, 0x00, 0x00]).unwrap().kind, Some(HubKind::CityHub));
+        // Unknown hubs are still parsed
+        let unknown = ManufacturerData::parse(&[0x00, 0x9f, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(unknown.kind, None);
+        assert_eq!(unknown.system_type_id, 0x9f);
+        // Too short
+        assert!(ManufacturerData::parse(&[0x00, 0x80]).is_none());
+    }
+
+    #[tokio::test]
+    async fn hub_kind_test() {
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        assert_eq!(hub.kind(), None);
+
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport_of_kind(transport, Some(HubKind::SpikePrimeHub)).await.unwrap();
+        assert_eq!(hub.clone().kind(), Some(HubKind::SpikePrimeHub));
+    }
+
+    #[test]
+    fn hub_ports_test() {
+        let technic = HubKind::TechnicHub.ports().unwrap();
+        assert_eq!(technic.led, Some(TechnicHubPorts::LED as u8));
+        assert_eq!(technic.tilt, Some(TechnicHubPorts::TILT as u8));
+        let city = HubKind::CityHub.ports().unwrap();
+        assert_eq!(city.voltage, Some(0x3c));
+        assert_eq!(city.accelerometer, None);
+        assert_eq!(HubKind::Mario.ports(), None);
+    }
+
+    #[tokio::test]
+    async fn built_in_device_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport_of_kind(transport, Some(HubKind::TechnicHub)).await.unwrap();
+        // Hub LED, gyro
+        peer.send(attached(0x32, 0x17)).unwrap();
+        peer.send(attached(0x62, 0x3a)).unwrap();
+        assert_eq!(hub.get_hub_led().await.unwrap().port_id, 0x32);
+        assert_eq!(hub.get_hub_gyro().await.unwrap().port_id, 0x62);
+
+        // A City hub has no gyro, and a hub of unknown kind no known ports
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport_of_kind(transport, Some(HubKind::CityHub)).await.unwrap();
+        assert!(hub.get_hub_gyro().await.is_err());
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        assert!(hub.get_hub_led().await.is_err());
+    }
+}
@@ -0,0 +1,58 @@
+extern crate rust_powered_lego;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            message_parameters::HubPropertiesProperties,
+            upstream_messages::HubPropertyValue,
+        },
+        HubPropertiesType,
+    };
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn get_fw_version_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        tokio::spawn(async move {
+            // [len, hub, HubProperties, FWVersion, RequestUpdate]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x01, 0x03, 0x05]);
+            // A battery update first, then the version 1.1.00.0004
+            peer.send(vec![0x06, 0x00, 0x01, 0x06, 0x06, 0x5a]).unwrap();
+            peer.send(vec![0x09, 0x00, 0x01, 0x03, 0x06, 0x04, 0x00, 0x00, 0x11]).unwrap();
+        });
+
+        let version = hub.get_fw_version().await.unwrap();
+        assert_eq!(version.to_string(), "1.1.00.0004");
+    }
+
+    #[tokio::test]
+    async fn property_updates_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x01, 0x02, 0x02]);
+            peer.send(vec![0x06, 0x00, 0x01, 0x02, 0x06, 0x01]).unwrap();
+            peer.send(vec![0x06, 0x00, 0x01, 0x02, 0x06, 0x00]).unwrap();
+            peer
+        });
+
+        let mut button = hub.enable_hub_property_updates(HubPropertiesProperties::Button).await.unwrap();
+        assert_eq!(button.next().await.unwrap(), HubPropertyValue::Button(true));
+        assert_eq!(button.next().await.unwrap(), HubPropertyValue::Button(false));
+    }
+
+    #[tokio::test]
+    async fn advertising_name_is_validated_test() {
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        assert!(hub.set_advertising_name("A name way too long").await.is_err());
+        assert!(hub.set_advertising_name("Technic").await.is_ok());
+    }
+}
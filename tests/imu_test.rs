@@ -0,0 +1,59 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::InMemoryTransport,
+        ports::Vector3,
+        HubType,
+        ThreeAxisSensorType,
+    };
+
+    use crate::common::attached;
+
+    // PortValueSingle of the tilt sensor: [z, y, x]
+    fn tilt(x: i16, y: i16, z: i16) -> Vec<u8> {
+        let mut frame = vec![0x0a, 0x00, 0x45, 0x63];
+        for axis in [z, y, x] {
+            frame.extend_from_slice(&axis.to_le_bytes());
+        }
+        frame
+    }
+
+    #[tokio::test]
+    async fn tilt_sensor_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // HubAttachedIO of the Technic hub's tilt sensor
+        peer.send(attached(0x63, 0x3b)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x63, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x63, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x63, 0x00]);
+            peer.send(tilt(20, -5, 10)).unwrap();
+            // Notifications on, for the tip over stream
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x63, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x63, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            peer
+        });
+
+        let sensor = hub.get_tilt_sensor(0x63).await.unwrap();
+        assert_eq!(sensor.read().await.unwrap(), Vector3 { x: 20.0, y: -5.0, z: -10.0 });
+
+        let mut tip_over = sensor.tip_over_stream(45.0).await.unwrap();
+        let peer = fake_hub.await.unwrap();
+        peer.send(tilt(10, 0, 0)).unwrap();
+        peer.send(tilt(12, 0, 0)).unwrap();
+        peer.send(tilt(12, -60, 0)).unwrap();
+        assert!(!tip_over.next().await.unwrap().value);
+        assert!(tip_over.next().await.unwrap().value);
+
+        assert!(hub.get_gyro(0x63).await.is_err());
+    }
+}
@@ -0,0 +1,58 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            consts::Color,
+        },
+        HubType,
+        LedType,
+    };
+
+    use crate::common::attached;
+
+    #[tokio::test]
+    async fn led_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // HubAttachedIO of the hub's LED
+        peer.send(attached(0x32, 0x17)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // RGB mode first
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x32, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x32, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            // [len, hub, PortOutputCommand, port, startup, WriteDirectModeData, mode, red, green, blue]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x81, 0x32, 0x10, 0x51, 0x01, 0xff, 0x80, 0x00]);
+            // Still in RGB mode
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x81, 0x32, 0x10, 0x51, 0x01, 0x00, 0x00, 0xff]);
+            // Blinking is done in color index mode
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x32, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x32, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, 0x32, 0x10, 0x51, 0x00, 0x09]);
+            assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, 0x32, 0x10, 0x51, 0x00, 0x00]);
+            peer
+        });
+
+        let led = hub.get_led(0x32).await.unwrap();
+        led.set_rgb(0xff, 0x80, 0x00).await.unwrap();
+        led.set_rgb(0x00, 0x00, 0xff).await.unwrap();
+        led.blink(Color::Red, Duration::from_millis(10), Some(1)).await.unwrap().unwrap();
+        let mut peer = fake_hub.await.unwrap();
+
+        // Blinking forever, until aborted
+        let blinking = led.blink(Color::Red, Duration::from_millis(10), None);
+        assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, 0x32, 0x10, 0x51, 0x00, 0x09]);
+        blinking.abort();
+        assert!(blinking.await.unwrap_err().is_cancelled());
+
+        assert!(hub.get_motor(0x32).await.is_err());
+    }
+}
@@ -0,0 +1,108 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::InMemoryTransport,
+        ports::MotorState,
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn motor_speed_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x00]);
+            peer.send(vec![0x05, 0x00, 0x45, 0x00, 0xce]).unwrap();
+            peer
+        });
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        assert_eq!(motor.speed().await.unwrap(), -50);
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_while_streaming_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // POS mode, notifications on
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            // The speed isn't asked for - the position is, in the streamed mode
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x00]);
+            peer.send(vec![0x08, 0x00, 0x45, 0x00, 0xd0, 0x02, 0x00, 0x00]).unwrap();
+            // The stream is gone, so the port may switch to SPEED
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x00]);
+            peer.send(vec![0x05, 0x00, 0x45, 0x00, 0xce]).unwrap();
+            peer
+        });
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        let positions = motor.position_stream(1).await.unwrap();
+        assert!(motor.speed().await.is_err());
+        assert!(motor.load().await.is_err());
+        assert_eq!(motor.position().await.unwrap(), 720);
+        drop(positions);
+        assert_eq!(motor.speed().await.unwrap(), -50);
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn motor_state_stream_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // Combination 0 is speed, position and absolute position, combination 1 adds the load.
+            // Asked only once - the setup reuses the combinations.
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x02]);
+            peer.send(vec![0x0b, 0x00, 0x43, 0x00, 0x02, 0x0e, 0x00, 0x1e, 0x00, 0x00, 0x00]).unwrap();
+            // VALUE FORMAT of each mode: 8, 32, 16 and 8 bits
+            for (mode, dataset_type) in [(0x01, 0x00), (0x02, 0x02), (0x03, 0x01), (0x04, 0x00)] {
+                assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x22, 0x00, mode, 0x80]);
+                peer.send(vec![0x0a, 0x00, 0x44, 0x00, mode, 0x80, 0x01, dataset_type, 0x04, 0x00]).unwrap();
+            }
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x02]);
+            for mode in [0x01, 0x02, 0x03, 0x04] {
+                assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01]);
+                peer.send(vec![0x0a, 0x00, 0x47, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            }
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x42, 0x00, 0x01, 0x01, 0x10, 0x20, 0x30, 0x40]);
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x03]);
+            peer.send(vec![0x07, 0x00, 0x48, 0x00, 0x81, 0x0f, 0x00]).unwrap();
+            // Speed 20, position 720, absolute position -90, load 5
+            peer.send(vec![0x0e, 0x00, 0x46, 0x00, 0x0f, 0x00, 0x14, 0xd0, 0x02, 0x00, 0x00, 0xa6, 0xff, 0x05]).unwrap();
+            // Only the speed changed
+            peer.send(vec![0x07, 0x00, 0x46, 0x00, 0x01, 0x00, 0x0a]).unwrap();
+            peer
+        });
+
+        let motor = hub.get_motor(0x00).await.unwrap();
+        let mut states = motor.state_stream(1).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+
+        let expected = MotorState { speed: 20, position: 720, apos: Some(-90), load: Some(5) };
+        assert_eq!(states.next().await.unwrap().value, expected);
+        assert_eq!(states.next().await.unwrap().value, MotorState { speed: 10, ..expected });
+    }
+}
@@ -0,0 +1,122 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            consts::PortInfoModeReplyCapabilities,
+            port_modes::{
+                DatasetType,
+                ValueRange,
+            },
+        },
+        HubType,
+    };
+
+    use crate::common::{attached, range};
+
+    // A single 16 bit value, -900..900 raw, -90..90 SI.
+    // Motor bias and capability bits are not supported.
+    fn mode_information(request: &[u8]) -> Vec<u8> {
+        let (port_id, info_type) = (request[3], request[5]);
+        let payload = match info_type {
+            0x00 => b"TEMP\0\0\0\0\0\0\0".to_vec(),
+            0x01 => range(-900.0, 900.0),
+            0x02 => range(-100.0, 100.0),
+            0x03 => range(-90.0, 90.0),
+            0x04 => b"DEG\0\0".to_vec(),
+            0x05 => vec![0x50, 0x00],
+            0x80 => vec![0x01, 0x01, 0x05, 0x01],
+            _ => return vec![0x05, 0x00, 0x05, 0x22, 0x05],
+        };
+        let mut reply = vec![0x00, 0x00, 0x44, port_id, 0x00, info_type];
+        reply.extend(payload);
+        reply[0] = reply.len() as u8;
+        reply
+    }
+
+    #[tokio::test]
+    async fn describe_port_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // Mode info: input only, a single mode
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x32, 0x01]);
+            peer.send(vec![0x0b, 0x00, 0x43, 0x32, 0x01, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00]).unwrap();
+            // [len, hub, PortModeInformationRequest, port, mode, info type]
+            while let Some(request) = peer.recv().await {
+                peer.send(mode_information(&request)).unwrap();
+                if request[5] == 0x80 {
+                    break;
+                }
+            }
+            peer
+        });
+
+        let description = hub.describe_port(0x32).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+
+        assert_eq!(description.capabilities, vec![PortInfoModeReplyCapabilities::Input]);
+        assert!(description.combinations.is_empty());
+        let mode = description.mode_by_name("TEMP").unwrap();
+        assert!(mode.is_input && !mode.is_output);
+        assert_eq!(mode.raw, ValueRange { min: -900.0, max: 900.0 });
+        assert_eq!(mode.si, ValueRange { min: -90.0, max: 90.0 });
+        assert_eq!(mode.symbol, "DEG");
+        assert!(mode.mapping.input.absolute && !mode.mapping.input.supports_null);
+        assert_eq!(mode.motor_bias, None);
+        assert_eq!(mode.capability_bits, None);
+        assert_eq!(mode.value_format.dataset_type, DatasetType::Bits16);
+        assert_eq!(mode.value_format.decimals, 1);
+    }
+
+    #[tokio::test]
+    async fn port_value_scaling_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        // Technic hub temperature sensor
+        peer.send(attached(0x3d, 0x3c)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x3d, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x3d, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            // The mode is described once
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x3d, 0x01]);
+            peer.send(vec![0x0b, 0x00, 0x43, 0x3d, 0x01, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00]).unwrap();
+            while let Some(request) = peer.recv().await {
+                peer.send(mode_information(&request)).unwrap();
+                if request[5] == 0x80 {
+                    break;
+                }
+            }
+            // Port value request
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x3d, 0x00]);
+            peer.send(vec![0x06, 0x00, 0x45, 0x3d, 0x84, 0x03]).unwrap();
+            peer
+        });
+
+        hub.setup_port_input_format(0x3d, 0x00, 1, true).await.unwrap();
+        let mut values = hub.get_decoded_port_value_stream(0x3d).await.unwrap();
+
+        let value = hub.get_port_value(0x3d).await.unwrap();
+        assert_eq!(value.raw, vec![900]);
+        assert_eq!(value.pct, vec![100.0]);
+        assert_eq!(value.si, vec![90.0]);
+        assert_eq!(value.symbol, "DEG");
+        assert_eq!(values.next().await.unwrap().raw, vec![900]);
+
+        // Negative values are sign extended
+        let peer = fake_hub.await.unwrap();
+        peer.send(vec![0x06, 0x00, 0x45, 0x3d, 0x3e, 0xfe]).unwrap();
+        let value = values.next().await.unwrap();
+        assert_eq!(value.raw, vec![-450]);
+        assert_eq!(value.si, vec![-45.0]);
+    }
+}
@@ -0,0 +1,114 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryPeer,
+            InMemoryTransport,
+        },
+        ports::{
+            BatteryEvent,
+            BatteryMonitor,
+            BatteryThresholds,
+        },
+        HubType,
+    };
+
+    use crate::common::{attached, range};
+
+    const VOLTAGE_PORT: u8 = 0x3c;
+    const CURRENT_PORT: u8 = 0x3b;
+
+    fn value(port_id: u8, raw: u16) -> Vec<u8> {
+        let mut frame = vec![0x06, 0x00, 0x45, port_id];
+        frame.extend_from_slice(&raw.to_le_bytes());
+        frame
+    }
+
+    // Answers whatever the sensors ask for. Raw 0..1000 is 0..10000 mV, or 0..5000 mA.
+    // Once both have their notifications enabled, sends a few values.
+    async fn fake_hub(mut peer: InMemoryPeer) -> InMemoryPeer {
+        let mut enabled = 0;
+        while let Some(request) = peer.recv().await {
+            let port_id = request[3];
+            let reply = match request[2] {
+                // Port input format setup, acknowledged as is
+                0x41 => {
+                    let mut reply = request.clone();
+                    reply[2] = 0x47;
+                    enabled += request[9];
+                    reply
+                },
+                // Mode info: input, two modes
+                0x21 => vec![0x0b, 0x00, 0x43, port_id, 0x01, 0x02, 0x02, 0x03, 0x00, 0x00, 0x00],
+                0x22 => {
+                    let si_max = if port_id == VOLTAGE_PORT { 10000.0 } else { 5000.0 };
+                    let payload = match request[5] {
+                        0x00 => b"VLT\0".to_vec(),
+                        0x01 => range(0.0, 1000.0),
+                        0x02 => range(0.0, 100.0),
+                        0x03 => range(0.0, si_max),
+                        0x04 => b"mV\0".to_vec(),
+                        0x05 => vec![0x10, 0x00],
+                        0x80 => vec![0x01, 0x01, 0x04, 0x00],
+                        _ => {
+                            peer.send(vec![0x05, 0x00, 0x05, 0x22, 0x05]).unwrap();
+                            continue;
+                        },
+                    };
+                    let mut reply = vec![0x00, 0x00, 0x44, port_id, request[4], request[5]];
+                    reply.extend(payload);
+                    reply[0] = reply.len() as u8;
+                    reply
+                },
+                other => panic!("Unexpected request {:#x}", other),
+            };
+            peer.send(reply).unwrap();
+            if enabled == 2 {
+                break;
+            }
+        }
+        peer.send(value(VOLTAGE_PORT, 600)).unwrap();
+        peer.send(value(CURRENT_PORT, 700)).unwrap();
+        peer.send(value(VOLTAGE_PORT, 700)).unwrap();
+        peer
+    }
+
+    #[tokio::test]
+    async fn battery_monitor_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached(VOLTAGE_PORT, 0x14)).unwrap();
+        peer.send(attached(CURRENT_PORT, 0x15)).unwrap();
+        let fake_hub = tokio::spawn(fake_hub(peer));
+
+        let monitor = BatteryMonitor::new(
+            hub.get_voltage_sensor(VOLTAGE_PORT).await.unwrap(),
+            hub.get_current_sensor(CURRENT_PORT).await.unwrap(),
+            BatteryThresholds {
+                low_voltage:            6500.0,
+                overcurrent:            3000.0,
+                overcurrent_duration:   Duration::ZERO,
+            },
+        );
+        let mut events = monitor.events().await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(events.next().await.unwrap().value);
+        }
+        assert!(received.contains(&BatteryEvent::Overcurrent(3500.0)));
+        // The voltage events keep their order
+        received.retain(|event| *event != BatteryEvent::Overcurrent(3500.0));
+        assert_eq!(received, vec![BatteryEvent::LowVoltage(6000.0), BatteryEvent::VoltageRecovered(7000.0)]);
+    }
+}
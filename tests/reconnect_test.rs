@@ -0,0 +1,190 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            ConnectionEvent,
+            InMemoryPeer,
+            InMemoryTransport,
+            ReconnectPolicy,
+            message_parameters::StartupAndCompletionInfo,
+            port_modes::CombinedModeSetup,
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::{attached_motor, attached_virtual};
+
+    const POLICY: ReconnectPolicy = ReconnectPolicy {
+        max_attempts:   2,
+        initial_delay:  Duration::from_millis(10),
+        max_delay:      Duration::from_millis(20),
+    };
+
+    // Plays the hub through a combined mode setup of speed and position on port 0
+    async fn combined_mode_setup(peer: &mut InMemoryPeer) {
+        assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x02]);
+        peer.send(vec![0x09, 0x00, 0x43, 0x00, 0x02, 0x06, 0x00, 0x00, 0x00]).unwrap();
+        for mode in [0x01, 0x02] {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x22, 0x00, mode, 0x80]);
+            peer.send(vec![0x0a, 0x00, 0x44, 0x00, mode, 0x80, 0x01, 0x00, 0x04, 0x00]).unwrap();
+        }
+        assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x02]);
+        for mode in [0x01, 0x02] {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, mode, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+        }
+        assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x42, 0x00, 0x01, 0x00, 0x10, 0x20]);
+        assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x42, 0x00, 0x03]);
+        peer.send(vec![0x07, 0x00, 0x48, 0x00, 0x80, 0x03, 0x00]).unwrap();
+    }
+
+    #[test]
+    fn backoff_test() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+        assert_eq!(policy.delay(40), Duration::from_secs(8));
+    }
+
+    #[tokio::test]
+    async fn reconnect_restores_setup_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reconnect_policy(Some(POLICY));
+        peer.send(attached_motor(0x00)).unwrap();
+        peer.send(attached_motor(0x01)).unwrap();
+        // Virtual port 0x10 of ports 0 and 1
+        peer.send(attached_virtual(0x10, 0x00, 0x01)).unwrap();
+
+        // Position notifications of port 0
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            peer
+        });
+        hub.setup_port_input_format(0x00, 0x02, 1, true).await.unwrap();
+        let mut peer = fake_hub.await.unwrap();
+        _ = hub.get_attached_device(0x10).await.unwrap();
+
+        let mut events = hub.get_connection_event_stream().await.unwrap();
+        peer.disconnect();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Disconnected);
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(1));
+
+        // Back again: the virtual port, then the input format
+        assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x61, 0x01, 0x00, 0x01]);
+        peer.send(attached_virtual(0x10, 0x00, 0x01)).unwrap();
+        assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]);
+        peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Connected);
+        assert!(hub.is_connected());
+    }
+
+    #[tokio::test]
+    async fn virtual_port_moved_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reconnect_policy(Some(POLICY));
+        peer.send(attached_motor(0x00)).unwrap();
+        peer.send(attached_motor(0x01)).unwrap();
+        peer.send(attached_virtual(0x10, 0x00, 0x01)).unwrap();
+        _ = hub.get_attached_device(0x10).await.unwrap();
+
+        let mut events = hub.get_connection_event_stream().await.unwrap();
+        peer.disconnect();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Disconnected);
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(1));
+
+        // The hub hands out another id this time
+        assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x61, 0x01, 0x00, 0x01]);
+        peer.send(attached_virtual(0x11, 0x00, 0x01)).unwrap();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::VirtualPortMoved(0x10, 0x11));
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Connected);
+        // Fetched again, the pair uses the new id
+        assert_eq!(hub.get_synced_motor_pair(0x00, 0x01).await.unwrap().port_id, 0x11);
+    }
+
+    #[tokio::test]
+    async fn reconnect_restores_combined_mode_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reconnect_policy(Some(POLICY));
+        peer.send(attached_motor(0x00)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            combined_mode_setup(&mut peer).await;
+            peer
+        });
+        let setup = CombinedModeSetup::new(0x00).mode(0x01, 1).mode(0x02, 1);
+        _ = hub.setup_port_combined_mode(setup).await.unwrap();
+        let mut peer = fake_hub.await.unwrap();
+
+        let mut events = hub.get_connection_event_stream().await.unwrap();
+        peer.disconnect();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Disconnected);
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(1));
+
+        // The same setup again, and no single input format on its own
+        combined_mode_setup(&mut peer).await;
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Connected);
+        assert_eq!(peer.try_recv(), None);
+    }
+
+    #[tokio::test]
+    async fn restore_failure_is_reported_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reconnect_policy(Some(POLICY));
+        peer.send(attached_motor(0x00)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            _ = peer.recv().await;
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            peer
+        });
+        hub.setup_port_input_format(0x00, 0x02, 1, true).await.unwrap();
+        let mut peer = fake_hub.await.unwrap();
+
+        let mut events = hub.get_connection_event_stream().await.unwrap();
+        peer.disconnect();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Disconnected);
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(1));
+
+        // Generic error: invalid use
+        _ = peer.recv().await;
+        peer.send(vec![0x05, 0x00, 0x05, 0x41, 0x06]).unwrap();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::RestoreFailed(0x00));
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Connected);
+    }
+
+    #[tokio::test]
+    async fn give_up_test() {
+        let (transport, peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reconnect_policy(Some(POLICY));
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+
+        let mut events = hub.get_connection_event_stream().await.unwrap();
+        peer.set_reachable(false);
+        peer.disconnect();
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Disconnected);
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(1));
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::Reconnecting(2));
+        assert_eq!(events.next().await.unwrap(), ConnectionEvent::GaveUp);
+
+        assert!(!hub.is_connected());
+        assert!(motor.start_power(50, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.is_err());
+    }
+}
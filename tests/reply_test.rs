@@ -0,0 +1,69 @@
+extern crate rust_powered_lego;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            ReplyTimeoutError,
+        },
+        HubType,
+    };
+
+    #[tokio::test]
+    async fn reply_is_matched_to_request_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        tokio::spawn(async move {
+            _ = peer.recv().await;
+            // Unrelated traffic: a port value and the mode info of another port
+            peer.send(vec![0x05, 0x00, 0x45, 0x01, 0x20]).unwrap();
+            peer.send(vec![0x0b, 0x00, 0x43, 0x02, 0x01, 0x0f, 0x06, 0x1e, 0x00, 0x1f, 0x00]).unwrap();
+            // The actual reply
+            peer.send(vec![0x0b, 0x00, 0x43, 0x01, 0x01, 0x0f, 0x04, 0x06, 0x00, 0x01, 0x00]).unwrap();
+        });
+
+        let reply = hub.get_port_info_mode(0x01).await.unwrap();
+        assert_eq!(reply.port_id, 0x01);
+        assert_eq!(reply.total_mode_count, 4);
+        assert_eq!(reply.input_modes, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn error_fails_only_its_request_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reply_timeout(Duration::from_millis(200));
+
+        let fake_hub = tokio::spawn(async move {
+            // Requests for different ports go out together
+            let first = peer.recv().await.unwrap();
+            let second = peer.recv().await.unwrap();
+            // The first one is rejected, the second one answered
+            peer.send(vec![0x05, 0x00, 0x05, 0x21, 0x06]).unwrap();
+            peer.send(vec![0x0b, 0x00, 0x43, second[3], 0x01, 0x0f, 0x04, 0x06, 0x00, 0x01, 0x00]).unwrap();
+            (first[3], peer)
+        });
+
+        let (a, b) = tokio::join!(hub.get_port_info_mode(0x00), hub.get_port_info_mode(0x01));
+        let (rejected_port, _peer) = fake_hub.await.unwrap();
+        let (rejected, answered) = if rejected_port == 0x00 { (a, b) } else { (b, a) };
+        // The error, not a timeout
+        assert!(rejected.unwrap_err().downcast_ref::<ReplyTimeoutError>().is_none());
+        assert_eq!(answered.unwrap().port_id, 1 - rejected_port);
+    }
+
+    #[tokio::test]
+    async fn reply_timeout_test() {
+        let (transport, _peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        hub.set_reply_timeout(Duration::from_millis(50));
+
+        let err = hub.get_port_info_mode(0x01).await.unwrap_err();
+        assert!(err.downcast_ref::<ReplyTimeoutError>().is_some());
+    }
+}
@@ -0,0 +1,34 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn motor_outlives_hub_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        let motor = hub.get_motor(0x00).await.unwrap();
+        drop(hub);
+
+        // The motor owns its handle, so a task can own the motor
+        let control_loop = tokio::spawn(async move {
+            motor.start_power(25, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.unwrap();
+        });
+        control_loop.await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), vec![0x08, 0x00, 0x81, 0x00, 0x10, 0x51, 0x00, 0x19]);
+    }
+}
@@ -0,0 +1,96 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::InMemoryTransport,
+        ports::{
+            ForceSensorEvent,
+            RawRgbI,
+        },
+        HubType,
+    };
+
+    use crate::common::attached;
+
+    #[tokio::test]
+    async fn color_sensor_rgb_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached(0x00, 0x3d)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x00, 0x00]);
+            peer.send(vec![0x0c, 0x00, 0x45, 0x00, 0x10, 0x00, 0x20, 0x00, 0x30, 0x00, 0x40, 0x00]).unwrap();
+            peer
+        });
+
+        let sensor = hub.get_technic_color_sensor(0x00).await.unwrap();
+        assert_eq!(sensor.rgb().await.unwrap(), RawRgbI { red: 0x10, green: 0x20, blue: 0x30, intensity: 0x40 });
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn distance_sensor_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached(0x02, 0x3e)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x21, 0x02, 0x00]);
+            // Nothing in range
+            peer.send(vec![0x06, 0x00, 0x45, 0x02, 0xff, 0xff]).unwrap();
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x02, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x02, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00]).unwrap();
+            // [len, hub, PortOutputCommand, port, startup, WriteDirectModeData, mode, top left, top right, bottom left, bottom right]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0b, 0x00, 0x81, 0x02, 0x10, 0x51, 0x05, 0x64, 0x00, 0x32, 0x00]);
+            peer
+        });
+
+        let sensor = hub.get_technic_distance_sensor(0x02).await.unwrap();
+        assert_eq!(sensor.distance().await.unwrap(), None);
+        sensor.set_lights(100, 0, 50, 0).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn force_sensor_events_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached(0x03, 0x3f)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x41, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]);
+            peer.send(vec![0x0a, 0x00, 0x47, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]).unwrap();
+            // 0, 2, 3, 6 and 0 newtons
+            for force in [0x00, 0x14, 0x1e, 0x3c, 0x00] {
+                peer.send(vec![0x05, 0x00, 0x45, 0x03, force]).unwrap();
+            }
+            peer
+        });
+
+        let sensor = hub.get_technic_force_sensor(0x03).await.unwrap();
+        let mut events = sensor.events(5.0).await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(events.next().await.unwrap().value);
+        }
+        assert_eq!(received, vec![
+            ForceSensorEvent::Released,
+            ForceSensorEvent::Touched,
+            ForceSensorEvent::Pressed,
+            ForceSensorEvent::Released,
+        ]);
+    }
+}
@@ -0,0 +1,45 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            CommandOutcome,
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+            consts::{
+                EndState,
+                Profile,
+            },
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn start_speed_for_time_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x02)).unwrap();
+        let motor = hub.get_motor(0x02).await.unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            let frame = peer.recv().await.unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x02, 0x01]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x02, 0x0a]).unwrap();
+            frame
+        });
+
+        let handle = motor.start_speed_for_time(
+            1500, -30, 80, EndState::BRAKE, Profile::AccDec, StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback).await.unwrap();
+        assert_eq!(handle.wait().await.unwrap(), CommandOutcome::Completed);
+
+        // [len, hub, PortOutputCommand, port, startup, StartSpeedForTime, time (2 bytes), speed, max power, end state, profile]
+        assert_eq!(fake_hub.await.unwrap(), vec![0x0c, 0x00, 0x81, 0x02, 0x11, 0x09, 0xdc, 0x05, 0xe2, 0x50, 0x7f, 0x03]);
+    }
+}
@@ -0,0 +1,55 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            CommandOutcome,
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+        },
+        HubType,
+        MotorType,
+    };
+
+    use crate::common::attached_motor;
+
+    #[tokio::test]
+    async fn motor_command_goes_through_transport_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x01)).unwrap();
+        let motor = hub.get_motor(0x01).await.unwrap();
+
+        // Plays the hub: checks the command and replies with a feedback
+        let fake_hub = tokio::spawn(async move {
+            let frame = peer.recv().await.unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x01, 0x01]).unwrap();
+            peer.send(vec![0x05, 0x00, 0x82, 0x01, 0x0a]).unwrap();
+            frame
+        });
+
+        let handle = motor.start_power(50, StartupAndCompletionInfo::ExecuteImmediatelyAndFeedback).await.unwrap();
+        assert_eq!(handle.wait().await.unwrap(), CommandOutcome::Completed);
+
+        // [len, hub, PortOutputCommand, port, startup, WriteDirectModeData, mode, power]
+        assert_eq!(fake_hub.await.unwrap(), vec![0x08, 0x00, 0x81, 0x01, 0x11, 0x51, 0x00, 0x32]);
+    }
+
+    #[tokio::test]
+    async fn lego_error_is_reported_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+
+        tokio::spawn(async move {
+            _ = peer.recv().await;
+            // Generic error: command not recognized
+            peer.send(vec![0x05, 0x00, 0x05, 0x21, 0x05]).unwrap();
+        });
+
+        assert!(hub.get_port_info_mode(0x01).await.is_err());
+    }
+}
@@ -0,0 +1,69 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::lego::{
+        UpstreamMessage,
+        consts::PortType,
+        upstream_messages::{
+            AttachedIOEvent,
+            PortInformationPayload,
+        },
+    };
+
+    use crate::common::attached_motor;
+
+    #[test]
+    fn parse_attached_io_test() {
+        // Technic large motor attached to port B
+        let frame = attached_motor(0x01);
+        match UpstreamMessage::parse(&frame).unwrap() {
+            UpstreamMessage::HubAttachedIO(msg) => {
+                assert_eq!(msg.port_id, 0x01);
+                match msg.event {
+                    AttachedIOEvent::AttachedIO { io_type, hardware_revision, .. } => {
+                        assert_eq!(io_type, Some(PortType::TechnicLargeLinearMotor));
+                        assert_eq!(hardware_revision.major, 1);
+                    },
+                    _ => panic!("Expected an attached io event"),
+                }
+            },
+            _ => panic!("Expected HubAttachedIO"),
+        }
+    }
+
+    #[test]
+    fn parse_mode_info_test() {
+        let frame = vec![0x0b, 0x00, 0x43, 0x00, 0x01, 0x0f, 0x06, 0x1e, 0x00, 0x1f, 0x00];
+        match UpstreamMessage::parse(&frame).unwrap() {
+            UpstreamMessage::PortInformation(msg) => {
+                assert_eq!(msg.info, PortInformationPayload::ModeInfo {
+                    capabilities:       0x0f,
+                    total_mode_count:   6,
+                    input_modes:        0x001e,
+                    output_modes:       0x001f,
+                });
+            },
+            _ => panic!("Expected PortInformation"),
+        }
+    }
+
+    #[test]
+    fn parse_truncated_frame_test() {
+        // Length says 11 bytes, but the mode info is cut short
+        let frame = vec![0x07, 0x00, 0x43, 0x00, 0x01, 0x0f, 0x06];
+        assert!(UpstreamMessage::parse(&frame).is_err());
+
+        // Length doesn't match the frame
+        let frame = vec![0x0b, 0x00, 0x43, 0x00];
+        assert!(UpstreamMessage::parse(&frame).is_err());
+    }
+
+    #[test]
+    fn parse_downstream_type_test() {
+        let frame = vec![0x05, 0x00, 0x21, 0x00, 0x01];
+        assert!(UpstreamMessage::parse(&frame).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+extern crate rust_powered_lego;
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use rust_powered_lego::{
+        hub::Hub,
+        lego::{
+            InMemoryTransport,
+            message_parameters::StartupAndCompletionInfo,
+            consts::Profile,
+        },
+        HubType,
+        SyncedMotorPairType,
+    };
+
+    use crate::common::{attached_motor, attached_virtual};
+
+    #[tokio::test]
+    async fn synced_motor_pair_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        peer.send(attached_motor(0x01)).unwrap();
+
+        let fake_hub = tokio::spawn(async move {
+            // [len, hub, VirtualPortSetup, connect, port A, port B]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x06, 0x00, 0x61, 0x01, 0x00, 0x01]);
+            // Virtual port 0x10 is attached
+            peer.send(attached_virtual(0x10, 0x00, 0x01)).unwrap();
+            // [len, hub, PortOutputCommand, port, startup, StartSpeedSync, speed A, speed B, max power, profile]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x81, 0x10, 0x10, 0x08, 0x32, 0xce, 0x64, 0x03]);
+            // [len, hub, VirtualPortSetup, disconnect, port]
+            assert_eq!(peer.recv().await.unwrap(), vec![0x05, 0x00, 0x61, 0x00, 0x10]);
+            peer.send(vec![0x05, 0x00, 0x04, 0x10, 0x00]).unwrap();
+            peer
+        });
+
+        let pair = hub.get_synced_motor_pair(0x00, 0x01).await.unwrap();
+        assert_eq!(pair.port_id, 0x10);
+
+        _ = pair.start_speed(50, -50, 100, Profile::AccDec, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.unwrap();
+
+        pair.release().await.unwrap();
+        let _peer = fake_hub.await.unwrap();
+        assert!(!hub.get_attached_devices().await.unwrap().contains_key(&0x10));
+    }
+
+    #[tokio::test]
+    async fn synced_motor_pair_either_order_test() {
+        let (transport, mut peer) = InMemoryTransport::new();
+        let hub = Hub::with_transport(transport).await.unwrap();
+        peer.send(attached_motor(0x00)).unwrap();
+        peer.send(attached_motor(0x01)).unwrap();
+        // Virtual port 0x10 of ports 0 and 1, already there
+        peer.send(attached_virtual(0x10, 0x00, 0x01)).unwrap();
+        _ = hub.get_attached_device(0x10).await.unwrap();
+
+        let pair = hub.get_synced_motor_pair(0x01, 0x00).await.unwrap();
+        assert_eq!(pair.port_id, 0x10);
+        // Speed A is of port 1, which is the second member of the virtual port
+        _ = pair.start_speed(50, -50, 100, Profile::AccDec, StartupAndCompletionInfo::ExecuteImmediatelyAndNoAction).await.unwrap();
+        assert_eq!(peer.recv().await.unwrap(), vec![0x0a, 0x00, 0x81, 0x10, 0x10, 0x08, 0xce, 0x32, 0x64, 0x03]);
+    }
+}